@@ -1,7 +1,14 @@
 CREATE UNLOGGED TABLE payments (
     correlationId UUID PRIMARY KEY,
     amount DECIMAL NOT NULL,
+    processor VARCHAR(32) NOT NULL,
     requested_at TIMESTAMP NOT NULL
 );
 
 CREATE INDEX payments_requested_at ON payments (requested_at);
+
+CREATE UNLOGGED TABLE reservations (
+    correlationId UUID PRIMARY KEY,
+    amount DECIMAL NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+PARAMS=()
+[ -n "$FROM" ] && PARAMS+=("from=${FROM}")
+[ -n "$TO" ] && PARAMS+=("to=${TO}")
+PARAMS+=("format=${FORMAT:-csv}")
+
+QUERY="?$(IFS='&'; echo "${PARAMS[*]}")"
+
+curl -sS -X GET -H "X-Rinha-Token: ${API_ADMIN_TOKEN:?API_ADMIN_TOKEN must be set}" "http://localhost:9999/admin/payments/export${QUERY}"
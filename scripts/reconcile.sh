@@ -0,0 +1,18 @@
+#!/bin/bash
+
+echo "=== Reconciling Ovelha summary with payment processors via nginx (localhost:9999) ==="
+echo
+
+PARAMS=()
+[ -n "$FROM" ] && PARAMS+=("from=${FROM}")
+[ -n "$TO" ] && PARAMS+=("to=${TO}")
+
+QUERY=""
+if [ ${#PARAMS[@]} -gt 0 ]; then
+  QUERY="?$(IFS='&'; echo "${PARAMS[*]}")"
+fi
+
+echo "Testing GET /admin/reconcile${QUERY}..."
+curl -X GET -H "X-Rinha-Token: ${API_ADMIN_TOKEN:?API_ADMIN_TOKEN must be set}" "http://localhost:9999/admin/reconcile${QUERY}"
+
+echo -e "\n\n=== Reconciliation completed ==="
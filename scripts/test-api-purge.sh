@@ -4,6 +4,6 @@ echo "=== Testing Pru API POST /purge-payments via nginx (localhost:9999) ==="
 echo
 
 echo "Testing POST /purge-payments..."
-curl -X POST http://localhost:9999/purge-payments
+curl -X POST -H "X-Rinha-Token: ${API_ADMIN_TOKEN:?API_ADMIN_TOKEN must be set}" http://localhost:9999/purge-payments
 
 echo -e "\n\n=== Purge test completed ==="
\ No newline at end of file
@@ -0,0 +1,102 @@
+use serde_json::json;
+
+use crate::headers::Headers;
+
+/// Same header the payment processors use for their admin endpoints
+pub const TOKEN_HEADER: &str = "X-Rinha-Token";
+
+/// Checks the admin token against API_ADMIN_TOKEN, answering 401 when it is
+/// missing and 403 when it doesn't match. Without API_ADMIN_TOKEN every admin
+/// request is refused, so no deployment falls back to a guessable token.
+pub fn authorize(headers: &Headers) -> Result<(), (u16, String)> {
+    check(headers, admin_token().as_deref())
+}
+
+fn check(headers: &Headers, expected: Option<&str>) -> Result<(), (u16, String)> {
+    let Some(expected) = expected else {
+        return Err((403, json!({"error": "Admin routes are disabled, API_ADMIN_TOKEN is not set"}).to_string()));
+    };
+
+    match headers.get(TOKEN_HEADER) {
+        None => Err((401, json!({"error": "Missing admin token"}).to_string())),
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        Some(_) => Err((403, json!({"error": "Invalid admin token"}).to_string())),
+    }
+}
+
+/// Compares every byte regardless of where the first mismatch is, so response
+/// timing doesn't reveal how much of the token was guessed right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The configured admin token, if any
+pub fn admin_token() -> Option<String> {
+    std::env::var("API_ADMIN_TOKEN").ok().filter(|token| !token.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(token: Option<&str>) -> Headers {
+        let mut headers = Headers::default();
+
+        if let Some(token) = token {
+            headers.insert(TOKEN_HEADER, token);
+        }
+
+        headers
+    }
+
+    fn status(token: Option<&str>, expected: Option<&str>) -> Option<u16> {
+        check(&headers(token), expected).err().map(|(status, _)| status)
+    }
+
+    #[test]
+    fn accepts_the_configured_token() {
+        assert_eq!(status(Some("s3cret"), Some("s3cret")), None);
+    }
+
+    #[test]
+    fn asks_for_a_missing_token() {
+        assert_eq!(status(None, Some("s3cret")), Some(401));
+    }
+
+    #[test]
+    fn refuses_a_wrong_token() {
+        assert_eq!(status(Some("s3cres"), Some("s3cret")), Some(403));
+        assert_eq!(status(Some("s3cret!"), Some("s3cret")), Some(403));
+        assert_eq!(status(Some(""), Some("s3cret")), Some(403));
+    }
+
+    #[test]
+    fn refuses_everyone_without_a_configured_token() {
+        assert_eq!(status(Some("s3cret"), None), Some(403));
+        assert_eq!(status(None, None), Some(403));
+    }
+
+    #[test]
+    fn treats_an_empty_admin_token_as_unset() {
+        std::env::set_var("API_ADMIN_TOKEN", "");
+        assert_eq!(admin_token(), None);
+
+        std::env::set_var("API_ADMIN_TOKEN", "s3cret");
+        assert_eq!(admin_token().as_deref(), Some("s3cret"));
+
+        std::env::remove_var("API_ADMIN_TOKEN");
+        assert_eq!(admin_token(), None);
+    }
+
+    #[test]
+    fn compares_tokens_of_any_length() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read, Write},
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mio::event::Source;
+use mio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+
+use crate::listener::{Listener, Stream};
+use crate::queue::Queue;
+use crate::publisher::Publisher;
+use crate::request::{Limits, Request, RequestError};
+use crate::store::PaymentStore;
+use crate::Response;
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+
+// How often idle connections are checked against their deadlines
+const TICK: Duration = Duration::from_millis(100);
+
+enum Acceptor {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+enum Socket {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+/// What a handler thread sends back for a connection
+enum Reply {
+    Complete(Vec<u8>),
+    /// Asks for the connection's socket in blocking mode, to stream a
+    /// response on it from the handler thread
+    Detach(mpsc::Sender<Stream>),
+}
+
+struct Connection {
+    stream: Socket,
+    buffer: Vec<u8>,
+    // Set once a complete request has been handed to a handler thread
+    dispatched: bool,
+    response: Option<Vec<u8>>,
+    written: usize,
+    deadline: Instant,
+}
+
+/// Readiness-based server: one thread multiplexes every connection's socket
+/// I/O, and only complete requests reach the handler threads, so slow
+/// clients never hold a handler.
+pub fn run<S: PaymentStore + 'static, P: Publisher + 'static>(
+    listener: Listener,
+    store: Arc<S>,
+    publisher: Arc<P>,
+    limits: Limits,
+    handler_threads: usize,
+) -> io::Result<()> {
+    let mut listener = match listener {
+        Listener::Tcp(listener) => {
+            listener.set_nonblocking(true)?;
+            Acceptor::Tcp(TcpListener::from_std(listener))
+        }
+        Listener::Unix(listener) => {
+            listener.set_nonblocking(true)?;
+            Acceptor::Unix(UnixListener::from_std(listener))
+        }
+    };
+
+    let mut poll = Poll::new()?;
+    poll.registry()
+        .register(listener.source(), LISTENER, Interest::READABLE)?;
+
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+    let jobs: Arc<Queue<(Token, Vec<u8>)>> = Arc::new(Queue::new());
+    let (responses, completed) = mpsc::channel::<(Token, Reply)>();
+
+    (0..handler_threads).for_each(|_| {
+        let jobs = Arc::clone(&jobs);
+        let store = Arc::clone(&store);
+        let publisher = Arc::clone(&publisher);
+        let waker = Arc::clone(&waker);
+        let responses = responses.clone();
+
+        thread::spawn(move || loop {
+            let (token, raw) = jobs.pop();
+            let request = Request::parse(Cursor::new(raw), &limits);
+
+            let stream = match crate::respond(request, store.as_ref(), publisher.as_ref()) {
+                Response::Complete(response) => {
+                    if responses.send((token, Reply::Complete(response.into_bytes()))).is_err() {
+                        break;
+                    }
+                    let _ = waker.wake();
+                    continue;
+                }
+                Response::Stream(stream) => stream,
+            };
+
+            // Streaming takes the connection off the event loop for good
+            let (detached, socket) = mpsc::channel();
+            if responses.send((token, Reply::Detach(detached))).is_err() {
+                break;
+            }
+            let _ = waker.wake();
+
+            // Dropped instead when the client went away in the meantime
+            if let Ok(mut socket) = socket.recv() {
+                let _ = socket.set_timeouts(limits.read_timeout, limits.write_timeout);
+
+                if let Err(e) = stream(&mut socket) {
+                    eprintln!("🐑 Streamed response aborted: {}", e);
+                }
+            }
+        });
+    });
+
+    let mut connections: HashMap<Token, Connection> = HashMap::new();
+    let mut next_token = WAKER.0 + 1;
+    let mut events = Events::with_capacity(1024);
+
+    loop {
+        if let Err(e) = poll.poll(&mut events, Some(TICK)) {
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        for event in events.iter() {
+            match event.token() {
+                LISTENER => loop {
+                    match listener.accept() {
+                        Ok(mut stream) => {
+                            let token = Token(next_token);
+                            next_token += 1;
+
+                            if let Err(e) = poll.registry().register(stream.source(), token, Interest::READABLE) {
+                                eprintln!("🐑 Failed to register connection: {}", e);
+                                continue;
+                            }
+                            connections.insert(
+                                token,
+                                Connection {
+                                    stream,
+                                    buffer: Vec::new(),
+                                    dispatched: false,
+                                    response: None,
+                                    written: 0,
+                                    deadline: Instant::now() + limits.read_timeout,
+                                },
+                            );
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            eprintln!("🐑 Failed to accept connection: {}", e);
+                            break;
+                        }
+                    }
+                },
+                WAKER => {
+                    for (token, reply) in completed.try_iter() {
+                        match reply {
+                            Reply::Complete(response) => {
+                                if let Some(connection) = connections.get_mut(&token) {
+                                    if let Err(e) = connection.respond(&poll, token, response, &limits) {
+                                        eprintln!("🐑 Failed to reregister connection: {}", e);
+                                        close(&poll, &mut connections, token);
+                                    }
+                                }
+                            }
+                            Reply::Detach(detached) => {
+                                if let Some(mut connection) = connections.remove(&token) {
+                                    let _ = poll.registry().deregister(connection.stream.source());
+
+                                    match connection.stream.into_blocking() {
+                                        Ok(socket) => {
+                                            let _ = detached.send(socket);
+                                        }
+                                        Err(e) => eprintln!("🐑 Failed to detach connection: {}", e),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                token => {
+                    let Some(connection) = connections.get_mut(&token) else {
+                        continue;
+                    };
+
+                    if event.is_readable() && !connection.dispatched {
+                        if !connection.fill_buffer(limits.max_request_size()) {
+                            close(&poll, &mut connections, token);
+                            continue;
+                        }
+
+                        match Request::frame_length(&connection.buffer, &limits) {
+                            Ok(Some(length)) => {
+                                connection.dispatched = true;
+                                connection.buffer.truncate(length);
+                                jobs.push((token, std::mem::take(&mut connection.buffer)));
+                            }
+                            // Full yet incomplete, so no request within the limits fits
+                            Ok(None) if connection.buffer.len() >= limits.max_request_size() => {
+                                let response = render_error(RequestError::BodyTooLarge);
+                                if let Err(e) = connection.respond(&poll, token, response, &limits) {
+                                    eprintln!("🐑 Failed to reregister connection: {}", e);
+                                    close(&poll, &mut connections, token);
+                                    continue;
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                let response = render_error(e);
+                                if let Err(e) = connection.respond(&poll, token, response, &limits) {
+                                    eprintln!("🐑 Failed to reregister connection: {}", e);
+                                    close(&poll, &mut connections, token);
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+
+                    if event.is_writable() && connection.response.is_some() && connection.flush() {
+                        close(&poll, &mut connections, token);
+                    }
+                }
+            }
+        }
+
+        expire(&poll, &mut connections, &limits);
+    }
+}
+
+impl Acceptor {
+    fn accept(&self) -> io::Result<Socket> {
+        match self {
+            Acceptor::Tcp(listener) => listener.accept().map(|(stream, _)| Socket::Tcp(stream)),
+            Acceptor::Unix(listener) => listener.accept().map(|(stream, _)| Socket::Unix(stream)),
+        }
+    }
+
+    fn source(&mut self) -> &mut dyn Source {
+        match self {
+            Acceptor::Tcp(listener) => listener,
+            Acceptor::Unix(listener) => listener,
+        }
+    }
+}
+
+impl Socket {
+    fn source(&mut self) -> &mut dyn Source {
+        match self {
+            Socket::Tcp(stream) => stream,
+            Socket::Unix(stream) => stream,
+        }
+    }
+
+    fn into_blocking(self) -> io::Result<Stream> {
+        match self {
+            Socket::Tcp(stream) => {
+                let stream = std::net::TcpStream::from(stream);
+                stream.set_nonblocking(false)?;
+                Ok(Stream::Tcp(stream))
+            }
+            Socket::Unix(stream) => {
+                let stream = std::os::unix::net::UnixStream::from(stream);
+                stream.set_nonblocking(false)?;
+                Ok(Stream::Unix(stream))
+            }
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.read(buf),
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.write(buf),
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.flush(),
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Connection {
+    /// Reads what is available, buffering at most `cap` bytes; returns false
+    /// once the peer has gone away
+    fn fill_buffer(&mut self, cap: usize) -> bool {
+        let mut chunk = [0u8; 4096];
+
+        while self.buffer.len() < cap {
+            let wanted = chunk.len().min(cap - self.buffer.len());
+
+            match self.stream.read(&mut chunk[..wanted]) {
+                Ok(0) => return false,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+
+    fn respond(&mut self, poll: &Poll, token: Token, response: Vec<u8>, limits: &Limits) -> io::Result<()> {
+        self.dispatched = true;
+        self.response = Some(response);
+        self.written = 0;
+        self.deadline = Instant::now() + limits.write_timeout;
+
+        poll.registry()
+            .reregister(self.stream.source(), token, Interest::WRITABLE)
+    }
+
+    /// Writes as much of the response as the socket accepts; returns true
+    /// when the connection is done, either fully written or broken
+    fn flush(&mut self) -> bool {
+        let Some(response) = &self.response else {
+            return false;
+        };
+
+        while self.written < response.len() {
+            match self.stream.write(&response[self.written..]) {
+                Ok(0) => return true,
+                Ok(written) => self.written += written,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return false,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return true,
+            }
+        }
+
+        true
+    }
+}
+
+/// Answers 408 to clients that stopped sending, and drops those that stopped reading
+fn expire(poll: &Poll, connections: &mut HashMap<Token, Connection>, limits: &Limits) {
+    let now = Instant::now();
+    let mut closed = Vec::new();
+
+    for (token, connection) in connections.iter_mut() {
+        if connection.deadline > now {
+            continue;
+        }
+
+        if connection.response.is_some() {
+            closed.push(*token);
+        } else if !connection.dispatched {
+            let response = render_error(RequestError::Timeout);
+            if let Err(e) = connection.respond(poll, *token, response, limits) {
+                eprintln!("🐑 Failed to reregister connection: {}", e);
+                closed.push(*token);
+            }
+        }
+    }
+
+    for token in closed {
+        close(poll, connections, token);
+    }
+}
+
+fn close(poll: &Poll, connections: &mut HashMap<Token, Connection>, token: Token) {
+    if let Some(mut connection) = connections.remove(&token) {
+        let _ = poll.registry().deregister(connection.stream.source());
+    }
+}
+
+fn render_error(error: RequestError) -> Vec<u8> {
+    let (status, body) = error.response();
+    crate::render(status, &body).into_bytes()
+}
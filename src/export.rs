@@ -0,0 +1,189 @@
+use chrono::SecondsFormat;
+use serde_json::json;
+use std::io::{self, Write};
+
+use crate::store::{Page, Payment, PaymentFilter, PaymentStore};
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    /// Picks the format from the `format` parameter, else from the Accept
+    /// header, defaulting to CSV
+    pub fn negotiate(format: Option<&str>, accept: Option<&str>) -> Result<Format, String> {
+        match format {
+            Some("csv") => Ok(Format::Csv),
+            Some("ndjson") => Ok(Format::Ndjson),
+            Some(other) => Err(format!("Unsupported format '{}', expected csv or ndjson", other)),
+            None if accept.is_some_and(|accept| accept.contains("ndjson")) => Ok(Format::Ndjson),
+            None => Ok(Format::Csv),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn row(&self, payment: &Payment) -> String {
+        let requested_at = payment.requested_at.to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        match self {
+            Format::Csv => format!(
+                "{},{},{},{}\n",
+                csv_field(&payment.correlation_id),
+                csv_field(&payment.processor),
+                payment.amount,
+                requested_at
+            ),
+            Format::Ndjson => format!(
+                "{}\n",
+                json!({
+                    "correlationId": payment.correlation_id,
+                    "processor": payment.processor,
+                    "amount": payment.amount,
+                    "requestedAt": requested_at
+                })
+            ),
+        }
+    }
+}
+
+/// Writes a chunked 200 response with every payment matching `filter`, one
+/// page (and one chunk) at a time, starting with the already fetched `first_page`.
+/// A store failure midway aborts without the final chunk, so clients can tell
+/// the export is incomplete.
+pub fn stream<S: PaymentStore>(
+    store: &S,
+    writer: &mut dyn Write,
+    format: Format,
+    filter: &PaymentFilter,
+    first_page: Page,
+    page_size: usize,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
+        format.content_type()
+    )?;
+
+    if let Format::Csv = format {
+        write_chunk(writer, "correlationId,processor,amount,requestedAt\n")?;
+    }
+
+    let mut page = first_page;
+
+    loop {
+        let rows: String = page.payments.iter().map(|payment| format.row(payment)).collect();
+        write_chunk(writer, &rows)?;
+
+        let Some(cursor) = page.next else {
+            break;
+        };
+
+        page = store
+            .payments(filter, Some(cursor), page_size)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+    }
+
+    writer.write_all(b"0\r\n\r\n")?;
+    writer.flush()
+}
+
+fn write_chunk(writer: &mut dyn Write, data: &str) -> io::Result<()> {
+    // An empty chunk would end the response early
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    write!(writer, "{:x}\r\n{}\r\n", data.len(), data)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn export(store: &MemoryStore, format: Format, page_size: usize) -> String {
+        let filter = PaymentFilter::default();
+        let first_page = store.payments(&filter, None, page_size).unwrap();
+        let mut response = Vec::new();
+
+        stream(store, &mut response, format, &filter, first_page, page_size).unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("default"), "default");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn prefers_the_format_parameter_over_accept() {
+        assert!(matches!(Format::negotiate(Some("csv"), Some("application/x-ndjson")), Ok(Format::Csv)));
+        assert!(matches!(Format::negotiate(Some("ndjson"), Some("text/csv")), Ok(Format::Ndjson)));
+        assert!(matches!(Format::negotiate(None, Some("application/x-ndjson")), Ok(Format::Ndjson)));
+        assert!(matches!(Format::negotiate(None, None), Ok(Format::Csv)));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(Format::negotiate(Some("xml"), None).is_err());
+        assert!(Format::negotiate(Some(""), Some("application/x-ndjson")).is_err());
+    }
+
+    #[test]
+    fn skips_empty_chunks() {
+        let mut written = Vec::new();
+        write_chunk(&mut written, "").unwrap();
+
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn ends_an_empty_export_with_a_single_last_chunk() {
+        let response = export(&MemoryStore::new(), Format::Ndjson, 10);
+
+        assert!(response.ends_with("\r\n\r\n0\r\n\r\n"));
+        assert_eq!(response.matches("0\r\n\r\n").count(), 1);
+    }
+
+    #[test]
+    fn streams_every_page_as_its_own_chunk() {
+        let store = MemoryStore::new();
+
+        for i in 0..3 {
+            let correlation_id = format!("4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b{}", i);
+            store.save(&correlation_id, "default", 1.5, &format!("2025-07-15T12:00:0{}.000Z", i)).unwrap();
+        }
+
+        let response = export(&store, Format::Csv, 2);
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+
+        assert_eq!(
+            body,
+            "2b\r\ncorrelationId,processor,amount,requestedAt\n\r\n\
+             94\r\n4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b0,default,1.5,2025-07-15T12:00:00.000Z\n\
+             4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b1,default,1.5,2025-07-15T12:00:01.000Z\n\r\n\
+             4a\r\n4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b2,default,1.5,2025-07-15T12:00:02.000Z\n\r\n\
+             0\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+#[derive(Debug, Default)]
+pub struct Headers {
+    // Names are stored lowercased, since HTTP header names are case-insensitive
+    pairs: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Parses a single "Name: value" line; malformed lines are ignored
+    pub fn parse_line(&mut self, line: &str) {
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+
+            if !name.is_empty() && !name.contains(char::is_whitespace) {
+                self.insert(name, value.trim());
+            }
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.pairs.push((name.to_ascii_lowercase(), value.to_string()));
+    }
+
+    /// First value for the header, as most handlers expect a single value
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
@@ -0,0 +1,8 @@
+//! Payment storage, queueing and processing, shared by the api and worker binaries
+
+pub mod processor;
+pub mod queue;
+pub mod redis_pool;
+pub mod retry;
+pub mod scheduler;
+pub mod store;
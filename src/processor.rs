@@ -0,0 +1,353 @@
+use serde_json::Value;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::queue::Queue;
+use crate::retry::RetryPolicy;
+use crate::scheduler::RetryScheduler;
+use crate::store::PaymentStore;
+
+/// Starts the payment worker threads, which take payloads from `payment_queue`
+/// and hand failed payments to `scheduler` for a delayed retry
+pub fn spawn_workers<S: PaymentStore + 'static, R: RetryScheduler + 'static>(
+    store: Arc<S>,
+    payment_queue: &Arc<Queue<Value>>,
+    scheduler: &Arc<R>,
+    thread_pool_size: usize,
+) {
+    for i in 0..thread_pool_size {
+        let queue = payment_queue.clone();
+        let store = store.clone();
+        let scheduler = scheduler.clone();
+        thread::spawn(move || {
+            println!("🐑 Payment worker {} started", i);
+            loop {
+                let payload = queue.pop();
+                process_payment(payload, store.as_ref(), scheduler.as_ref());
+            }
+        });
+    }
+}
+
+/// Starts the thread that moves retries back onto `payment_queue` once due
+pub fn spawn_retry_scheduler<R: RetryScheduler + 'static>(scheduler: Arc<R>, payment_queue: &Arc<Queue<Value>>) {
+    let poll_interval_ms: u64 = std::env::var("WORKER_RETRY_POLL_MS")
+        .unwrap_or_else(|_| "50".to_string())
+        .parse()
+        .unwrap_or(50);
+
+    let batch_size: usize = std::env::var("WORKER_RETRY_BATCH_SIZE")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .unwrap_or(100);
+
+    let queue = payment_queue.clone();
+    thread::spawn(move || {
+        println!("🐑 Retry scheduler started");
+        loop {
+            let now = chrono::Utc::now().timestamp_millis();
+
+            match scheduler.take_due(now, batch_size) {
+                Ok(due) => {
+                    let drained = due.len() < batch_size;
+
+                    for payload in due {
+                        queue.push(payload);
+                    }
+
+                    // A full batch may have left more due retries behind
+                    if !drained {
+                        continue;
+                    }
+                }
+                Err(e) => eprintln!("🐑 Retry scheduler error: {}", e),
+            }
+
+            thread::sleep(Duration::from_millis(poll_interval_ms));
+        }
+    });
+}
+
+fn process_payment<S: PaymentStore, R: RetryScheduler>(payload: Value, store: &S, scheduler: &R) {
+    let correlation_id = payload["correlationId"].as_str().unwrap_or("");
+    let amount = payload["amount"].as_f64().unwrap_or(0.0);
+    let requested_at = payload["requestedAt"].as_str().unwrap_or("");
+
+    // Configuration from environment variables
+    let default_timeout_ms: u64 = std::env::var("WORKER_DEFAULT_TIMEOUT_MS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .unwrap_or(300);
+
+    let fallback_timeout_ms: u64 = std::env::var("WORKER_FALLBACK_TIMEOUT_MS")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .unwrap_or(100);
+
+    let lookup_timeout_ms: u64 = std::env::var("WORKER_LOOKUP_TIMEOUT_MS")
+        .unwrap_or_else(|_| "100".to_string())
+        .parse()
+        .unwrap_or(100);
+
+    // Get current retry count from payload or default to 0
+    let current_retry_count = payload["_retry_count"].as_u64().unwrap_or(0) as usize;
+
+    // Only check is_processed for retried payments to avoid latency on first attempts
+    if current_retry_count > 0 && store.is_processed(correlation_id) {
+        println!(
+            "🐑 Payment {} already processed (retry {}), skipping",
+            correlation_id, current_retry_count
+        );
+        return;
+    }
+
+    // Deadlines count from when the API accepted the payment, not from this attempt
+    let started_at = chrono::DateTime::parse_from_rfc3339(requested_at)
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .unwrap_or_else(|_| chrono::Utc::now());
+
+    let lookup_timeout = Duration::from_millis(lookup_timeout_ms);
+
+    // The queued payload also carries the retry bookkeeping, which is ours alone
+    let body = serde_json::json!({
+        "correlationId": correlation_id,
+        "amount": amount,
+        "requestedAt": requested_at,
+    });
+
+    // A previous round couldn't tell whether a processor charged it, so nothing
+    // is sent again until both processors answer the lookup
+    if payload["_unconfirmed"].as_bool().unwrap_or(false) {
+        match confirm_processed(correlation_id, lookup_timeout) {
+            Lookup::Confirmed(processor) => {
+                record(store, correlation_id, processor, amount, requested_at);
+                return;
+            }
+            Lookup::Unknown => {
+                reschedule(&payload, scheduler, started_at, current_retry_count, true);
+                return;
+            }
+            Lookup::NotFound => {}
+        }
+    }
+
+    for (processor_name, timeout_ms) in [("default", default_timeout_ms), ("fallback", fallback_timeout_ms)] {
+        let mut backoff = processor_policy(processor_name).backoff(started_at);
+
+        while !backoff.exhausted() {
+            match try_processor(processor_name, &body, Duration::from_millis(timeout_ms)) {
+                Outcome::Accepted => {
+                    record(store, correlation_id, processor_name, amount, requested_at);
+                    return;
+                }
+                Outcome::Ambiguous => match confirm_processed(correlation_id, lookup_timeout) {
+                    Lookup::Confirmed(processor) => {
+                        record(store, correlation_id, processor, amount, requested_at);
+                        return;
+                    }
+                    // Trying any processor now could charge the payment twice
+                    Lookup::Unknown => {
+                        reschedule(&payload, scheduler, started_at, current_retry_count, true);
+                        return;
+                    }
+                    Lookup::NotFound => {}
+                },
+                Outcome::Rejected => {}
+            }
+
+            match backoff.next_delay() {
+                Some(delay) => thread::sleep(delay),
+                None => break,
+            }
+        }
+    }
+
+    reschedule(&payload, scheduler, started_at, current_retry_count, false);
+}
+
+/// Schedules a delayed retry with the attempt counted, or gives up once the
+/// retry policy is exhausted. `unconfirmed` makes the next round look the
+/// payment up before sending it anywhere.
+fn reschedule<R: RetryScheduler>(
+    payload: &Value,
+    scheduler: &R,
+    started_at: chrono::DateTime<chrono::Utc>,
+    current_retry_count: usize,
+    unconfirmed: bool,
+) {
+    let correlation_id = payload["correlationId"].as_str().unwrap_or("");
+    let previous_delay = payload["_retry_delay_ms"].as_u64().map(Duration::from_millis);
+    let retry_policy = scheduled_retry_policy();
+    let mut backoff = retry_policy.resume(
+        started_at,
+        current_retry_count,
+        previous_delay.unwrap_or(retry_policy.base_delay),
+    );
+
+    let Some(delay) = backoff.next_delay() else {
+        eprintln!(
+            "🐑 Payment {} permanently failed after {} retries",
+            correlation_id, current_retry_count
+        );
+        return;
+    };
+
+    let reason = if unconfirmed { "Outcome unknown" } else { "Both processors failed" };
+    println!(
+        "🐑 {} for {} - retrying in {}ms ({}/{})",
+        reason,
+        correlation_id,
+        delay.as_millis(),
+        current_retry_count + 1,
+        retry_policy.max_attempts - 1
+    );
+
+    let mut retry = payload.clone();
+    retry["_retry_count"] = Value::from(current_retry_count + 1);
+    retry["_retry_delay_ms"] = Value::from(delay.as_millis() as u64);
+    retry["_unconfirmed"] = Value::from(unconfirmed);
+    let due = chrono::Utc::now().timestamp_millis() + delay.as_millis() as i64;
+
+    if let Err(e) = redis_policy().retry(|| scheduler.schedule(&retry, due)) {
+        eprintln!("🐑 Failed to schedule retry for {}: {}", correlation_id, e);
+    }
+}
+
+/// Attempts against one processor within a round: the default gets
+/// WORKER_MAX_ATTEMPTS, the fallback a single try, each overridable through
+/// WORKER_{PROCESSOR}_* (see `RetryPolicy::from_env`). WORKER_PAYMENT_DEADLINE_MS
+/// sets the deadline for both.
+fn processor_policy(processor_name: &str) -> RetryPolicy {
+    let max_attempts: usize = std::env::var("WORKER_MAX_ATTEMPTS")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse()
+        .unwrap_or(3);
+
+    let backoff_sleep_ms: u64 = std::env::var("WORKER_BACKOFF_SLEEP_MS")
+        .unwrap_or_else(|_| "2".to_string())
+        .parse()
+        .unwrap_or(2);
+
+    let deadline_ms: u64 = std::env::var("WORKER_PAYMENT_DEADLINE_MS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+
+    let defaults = RetryPolicy {
+        max_attempts: if processor_name == "default" { max_attempts } else { 1 },
+        base_delay: Duration::from_millis(backoff_sleep_ms),
+        max_delay: Duration::from_millis(50),
+        deadline: (deadline_ms > 0).then(|| Duration::from_millis(deadline_ms)),
+    };
+
+    RetryPolicy::from_env(processor_name, defaults)
+}
+
+/// Rounds through both processors, rescheduled while WORKER_MAX_RETRIES allows,
+/// overridable through WORKER_RETRY_*
+fn scheduled_retry_policy() -> RetryPolicy {
+    let max_retries: usize = std::env::var("WORKER_MAX_RETRIES")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse()
+        .unwrap_or(3);
+
+    let deadline_ms: u64 = std::env::var("WORKER_PAYMENT_DEADLINE_MS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+
+    let defaults = RetryPolicy {
+        max_attempts: max_retries + 1,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_millis(5000),
+        deadline: (deadline_ms > 0).then(|| Duration::from_millis(deadline_ms)),
+    };
+
+    RetryPolicy::from_env("retry", defaults)
+}
+
+/// Redis writes a worker can't afford to lose, such as saving a payment the
+/// processor already accepted, so no deadline applies. Overridable through WORKER_REDIS_*.
+fn redis_policy() -> RetryPolicy {
+    let defaults = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(5),
+        max_delay: Duration::from_millis(100),
+        deadline: None,
+    };
+
+    RetryPolicy::from_env("redis", defaults)
+}
+
+enum Outcome {
+    Accepted,
+    Rejected,
+    /// The request may have reached the processor, e.g. it timed out waiting for the response
+    Ambiguous,
+}
+
+fn try_processor(processor_name: &str, body: &Value, timeout: Duration) -> Outcome {
+    let endpoint = format!("http://payment-processor-{}:8080/payments", processor_name);
+
+    match ureq::post(&endpoint)
+        .timeout(timeout)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+    {
+        Ok(response) if response.status() >= 200 && response.status() < 300 => Outcome::Accepted,
+        Ok(_) | Err(ureq::Error::Status(_, _)) => Outcome::Rejected,
+        Err(ureq::Error::Transport(transport)) => match transport.kind() {
+            // The request never left, so no processor could have charged it
+            ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed => Outcome::Rejected,
+            _ => Outcome::Ambiguous,
+        },
+    }
+}
+
+enum Lookup {
+    Confirmed(&'static str),
+    /// Both processors answered 404
+    NotFound,
+    /// A lookup failed or timed out, so the payment may have been charged
+    Unknown,
+}
+
+/// After an ambiguous outcome, asks both processors whether they accepted the
+/// payment before it is retried anywhere, so it is never charged twice
+fn confirm_processed(correlation_id: &str, timeout: Duration) -> Lookup {
+    let mut lookup = Lookup::NotFound;
+
+    for processor_name in ["default", "fallback"] {
+        let endpoint = format!(
+            "http://payment-processor-{}:8080/payments/{}",
+            processor_name, correlation_id
+        );
+
+        match ureq::get(&endpoint).timeout(timeout).call() {
+            Ok(response) if response.status() == 200 => return Lookup::Confirmed(processor_name),
+            Err(ureq::Error::Status(404, _)) => {}
+            _ => lookup = Lookup::Unknown,
+        }
+    }
+
+    lookup
+}
+
+fn record<S: PaymentStore>(store: &S, correlation_id: &str, processor: &str, amount: f64, requested_at: &str) {
+    // Atomic save - returns true if saved, false if already existed
+    match redis_policy().retry(|| store.save(correlation_id, processor, amount, requested_at)) {
+        Ok(true) => {
+            println!("🐑 Payment {} processed by {}", correlation_id, processor);
+        }
+        Ok(false) => {
+            println!(
+                "🐑 Payment {} already saved by another worker",
+                correlation_id
+            );
+        }
+        Err(e) => {
+            eprintln!("🐑 Error saving payment {}: {}", correlation_id, e);
+        }
+    }
+}
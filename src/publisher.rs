@@ -0,0 +1,30 @@
+use serde_json::Value;
+
+use crate::queue::Queue;
+use crate::redis_pool::ConnectionPool;
+
+/// Hands payments over to the workers: through the Redis `payments` channel
+/// when API and worker run apart, or an in-process queue in embedded mode
+pub trait Publisher: Send + Sync {
+    fn publish(&self, payload: &Value) -> Result<(), String>;
+}
+
+impl Publisher for ConnectionPool {
+    fn publish(&self, payload: &Value) -> Result<(), String> {
+        let mut conn = self.get().map_err(|_| "Redis connection failed".to_string())?;
+
+        redis::cmd("PUBLISH")
+            .arg("payments")
+            .arg(payload.to_string())
+            .query::<i32>(&mut *conn)
+            .map(|_| ())
+            .map_err(|_| "Redis publish failed".to_string())
+    }
+}
+
+impl Publisher for Queue<Value> {
+    fn publish(&self, payload: &Value) -> Result<(), String> {
+        self.push(payload.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+#[derive(Debug, Default)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn parse(query_string: &str) -> Query {
+        let mut query = Query::default();
+
+        for param in query_string.split('&') {
+            if param.is_empty() {
+                continue;
+            }
+
+            // Keys without "=" are kept with an empty value (e.g. "?debug")
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            query.pairs.push((decode(key), decode(value)));
+        }
+
+        query
+    }
+
+    /// First value for the key, as most handlers expect a single value
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Decodes application/x-www-form-urlencoded text: "+" becomes a space and
+/// "%XX" sequences become bytes. Malformed sequences are kept as-is.
+fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match bytes.get(i + 1..i + 3).and_then(hex_byte) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    if !pair.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+
+    let hex = std::str::from_utf8(pair).ok()?;
+    u8::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pairs_in_order() {
+        let query = Query::parse("from=2025-07-15T12:00:00Z&processor=default&processor=fallback");
+
+        assert_eq!(query.get("from"), Some("2025-07-15T12:00:00Z"));
+        assert_eq!(query.get("processor"), Some("default"));
+        assert_eq!(query.get("to"), None);
+    }
+
+    #[test]
+    fn keeps_keys_without_a_value() {
+        let query = Query::parse("debug&&total=true");
+
+        assert_eq!(query.get("debug"), Some(""));
+        assert_eq!(query.get("total"), Some("true"));
+    }
+
+    #[test]
+    fn decodes_keys_and_values() {
+        let query = Query::parse("from=2025-07-15T12%3A00%3A00%2B03%3A00&na%6De=a+b");
+
+        assert_eq!(query.get("from"), Some("2025-07-15T12:00:00+03:00"));
+        assert_eq!(query.get("name"), Some("a b"));
+    }
+
+    #[test]
+    fn keeps_malformed_escapes_as_is() {
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+        assert_eq!(decode("%41%"), "A%");
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        assert_eq!(decode("%FF"), "\u{FFFD}");
+    }
+}
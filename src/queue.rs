@@ -5,6 +5,12 @@ pub struct Queue<T> {
     emitter: Condvar
 }
 
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Queue<T> {
     pub fn new() -> Queue<T> {
         Self {
@@ -0,0 +1,80 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::store::PaymentStore;
+
+/// Compares our summary for the window against each processor's admin summary
+/// and reports the count/amount deltas (ours minus theirs) per processor
+pub fn reconcile<S: PaymentStore>(store: &S, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<Value, String> {
+    let ours = store
+        .summary(from, to)
+        .map_err(|e| format!("Failed to read summary: {}", e))?;
+
+    let token = std::env::var("PROCESSOR_ADMIN_TOKEN").unwrap_or_else(|_| "123".to_string());
+    let timeout_ms: u64 = std::env::var("PROCESSOR_ADMIN_TIMEOUT_MS")
+        .unwrap_or_else(|_| "2000".to_string())
+        .parse()
+        .unwrap_or(2000);
+
+    let mut report = json!({});
+    let processors: Vec<&String> = ours.as_object().map(|summary| summary.keys().collect()).unwrap_or_default();
+
+    for processor in processors {
+        let our_requests = ours[processor]["totalRequests"].as_i64().unwrap_or(0);
+        let our_amount = ours[processor]["totalAmount"].as_f64().unwrap_or(0.0);
+
+        report[processor.as_str()] = match processor_summary(processor, from, to, &token, Duration::from_millis(timeout_ms)) {
+            Ok(theirs) => {
+                let their_requests = theirs["totalRequests"].as_i64().unwrap_or(0);
+                let their_amount = theirs["totalAmount"].as_f64().unwrap_or(0.0);
+
+                json!({
+                    "ours": {"totalRequests": our_requests, "totalAmount": our_amount},
+                    "theirs": {"totalRequests": their_requests, "totalAmount": their_amount},
+                    "delta": {
+                        "totalRequests": our_requests - their_requests,
+                        "totalAmount": ((our_amount - their_amount) * 100.0).round() / 100.0
+                    },
+                    "consistent": our_requests == their_requests
+                        && ((our_amount - their_amount) * 100.0).round() == 0.0
+                })
+            }
+            Err(e) => json!({
+                "ours": {"totalRequests": our_requests, "totalAmount": our_amount},
+                "error": e
+            }),
+        };
+    }
+
+    Ok(report)
+}
+
+fn processor_summary(
+    processor: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    token: &str,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let endpoint = format!("http://payment-processor-{}:8080/admin/payments-summary", processor);
+
+    let mut request = ureq::get(&endpoint)
+        .timeout(timeout)
+        .set("X-Rinha-Token", token);
+
+    if let Some(from) = from {
+        request = request.query("from", &from.to_rfc3339_opts(SecondsFormat::Millis, true));
+    }
+    if let Some(to) = to {
+        request = request.query("to", &to.to_rfc3339_opts(SecondsFormat::Millis, true));
+    }
+
+    let body = request
+        .call()
+        .map_err(|e| format!("Failed to reach {} processor: {}", processor, e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read {} processor response: {}", processor, e))?;
+
+    serde_json::from_str(&body).map_err(|e| format!("Invalid summary from {} processor: {}", processor, e))
+}
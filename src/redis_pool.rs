@@ -3,6 +3,7 @@ use redis::{Client, Connection, RedisResult};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
+#[allow(dead_code)]
 pub struct ConnectionPool {
     queue: Arc<Queue<Connection>>,
     client: Client,
@@ -1,81 +1,504 @@
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader, Read},
-    net::TcpStream,
+    io::{self, BufRead, Read},
+    time::Duration,
 };
 
-use serde_json::Value;
+use serde_json::{json, Value};
+
+use crate::headers::Headers;
+use crate::query::Query;
 
 #[derive(Debug)]
 pub struct Request {
     pub route: String,
-    pub params: HashMap<String, String>,
+    pub params: Query,
+    pub headers: Headers,
     pub body: Option<Value>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Maximum bytes for the request line plus all header lines
+    pub max_header_size: usize,
+    pub max_header_count: usize,
+    pub max_body_size: u64,
+    pub read_timeout: Duration,
+    pub write_timeout: Duration,
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    Timeout,
+    HeaderTooLarge,
+    BodyTooLarge,
+    Malformed,
+}
+
+impl Limits {
+    /// Largest request either parser accepts: headers, body, and up to
+    /// `max_header_size` more of chunk framing (size lines, CRLFs, trailers)
+    pub fn max_request_size(&self) -> usize {
+        self.max_header_size
+            .saturating_mul(2)
+            .saturating_add(usize::try_from(self.max_body_size).unwrap_or(usize::MAX))
+    }
+}
+
+impl RequestError {
+    pub fn response(&self) -> (u16, String) {
+        match self {
+            RequestError::Timeout => (408, json!({"error": "Request timed out"}).to_string()),
+            RequestError::HeaderTooLarge => {
+                (431, json!({"error": "Request header fields too large"}).to_string())
+            }
+            RequestError::BodyTooLarge => (413, json!({"error": "Payload too large"}).to_string()),
+            RequestError::Malformed => (400, json!({"error": "Malformed request"}).to_string()),
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RequestError::Timeout,
+            _ => RequestError::Malformed,
+        }
+    }
+}
+
 impl Request {
     fn new() -> Self {
         Self {
             route: String::new(),
-            params: HashMap::new(),
+            params: Query::default(),
+            headers: Headers::default(),
             body: None,
         }
     }
 
-    pub fn parse(mut reader: BufReader<&mut TcpStream>) -> Request {
+    pub fn parse<R: BufRead>(mut reader: R, limits: &Limits) -> Result<Request, RequestError> {
         let mut request = Self::new();
-        let mut headline = String::new();
+        let mut line = Vec::new();
+        let mut header_budget = limits.max_header_size;
 
-        let _ = reader.read_line(&mut headline);
+        read_line(&mut reader, &mut line, &mut header_budget)?;
+        let headline = String::from_utf8_lossy(&line).into_owned();
 
         let headline_parts: Vec<&str> = headline.split_whitespace().collect();
         if headline_parts.len() >= 2 {
             let method = headline_parts[0];
             let path = headline_parts[1];
 
-            if path.contains('?') {
-                let parts: Vec<&str> = path.split('?').collect();
-                let base_path = parts[0];
-                let query_string = parts[1];
-
+            if let Some((base_path, query_string)) = path.split_once('?') {
                 request.route = format!("{} {}", method, base_path);
-
-                for param in query_string.split('&') {
-                    if let Some((key, value)) = param.split_once('=') {
-                        request.params.insert(key.to_string(), value.to_string());
-                    }
-                }
+                request.params = Query::parse(query_string);
             } else {
                 request.route = format!("{} {}", method, path);
             }
 
-            let mut content_length: u64 = 0;
-
-            for line in reader.by_ref().lines() {
-                let line = line.unwrap();
-                
-                if line.to_lowercase().starts_with("content-length:") {
-                    let parts: Vec<&str> = line.split(':').collect();
-                    if parts.len() >= 2 {
-                        content_length = parts[1].trim().parse::<u64>().unwrap_or(0);
-                    }
+            // Header lines are read as raw bytes so invalid UTF-8 can't panic the thread
+            let mut header_count = 0;
+            loop {
+                if read_line(&mut reader, &mut line, &mut header_budget)? == 0 {
+                    break;
                 }
 
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\r', '\n']);
+
                 if line.is_empty() {
                     break;
                 }
+
+                header_count += 1;
+                if header_count > limits.max_header_count {
+                    return Err(RequestError::HeaderTooLarge);
+                }
+
+                request.headers.parse_line(line);
             }
 
-            if content_length > 0 {
-                let mut body = String::new();
-                let _ = reader.take(content_length).read_to_string(&mut body);
+            let mut body = Vec::new();
 
-                if let Ok(parsed) = serde_json::from_str(&body) {
+            if is_chunked(&request.headers) {
+                read_chunked_body(&mut reader, &mut body, limits)?;
+            } else {
+                let content_length = content_length(&request.headers);
+
+                if content_length > limits.max_body_size {
+                    return Err(RequestError::BodyTooLarge);
+                }
+
+                if content_length > 0 {
+                    reader.take(content_length).read_to_end(&mut body)?;
+                }
+            }
+
+            if !body.is_empty() {
+                if let Ok(parsed) = serde_json::from_slice(&body) {
                     request.body = Some(parsed);
                 }
             }
         }
 
-        request
+        Ok(request)
+    }
+
+    /// Length of the first complete request in `buffer`, or `None` while more
+    /// bytes are needed. Lets non-blocking servers buffer a whole request
+    /// before handing it to `parse`, applying the same limits along the way.
+    pub fn frame_length(buffer: &[u8], limits: &Limits) -> Result<Option<usize>, RequestError> {
+        let mut headers = Headers::default();
+        let mut header_count = 0;
+        let mut position = match next_line(buffer, 0) {
+            Some(end) => end,
+            None => return incomplete_header(buffer, limits),
+        };
+
+        loop {
+            let end = match next_line(buffer, position) {
+                Some(end) => end,
+                None => return incomplete_header(buffer, limits),
+            };
+
+            if end > limits.max_header_size {
+                return Err(RequestError::HeaderTooLarge);
+            }
+
+            let line = String::from_utf8_lossy(&buffer[position..end]);
+            let line = line.trim_end_matches(['\r', '\n']);
+            position = end;
+
+            if line.is_empty() {
+                break;
+            }
+
+            header_count += 1;
+            if header_count > limits.max_header_count {
+                return Err(RequestError::HeaderTooLarge);
+            }
+
+            headers.parse_line(line);
+        }
+
+        if !is_chunked(&headers) {
+            let content_length = content_length(&headers);
+
+            if content_length > limits.max_body_size {
+                return Err(RequestError::BodyTooLarge);
+            }
+
+            let total = position + content_length as usize;
+            return Ok((buffer.len() >= total).then_some(total));
+        }
+
+        let mut body_size: u64 = 0;
+        let mut framing_budget = limits.max_header_size;
+        loop {
+            let end = match buffered_line(buffer, position, &mut framing_budget)? {
+                Some(end) => end,
+                None => return Ok(None),
+            };
+
+            let size_line = String::from_utf8_lossy(&buffer[position..end]);
+            let size_hex = size_line.split(';').next().unwrap_or("").trim();
+            let size = u64::from_str_radix(size_hex, 16).map_err(|_| RequestError::Malformed)?;
+            position = end;
+
+            if size == 0 {
+                break;
+            }
+
+            body_size = body_size.saturating_add(size);
+            if body_size > limits.max_body_size {
+                return Err(RequestError::BodyTooLarge);
+            }
+
+            // Chunk data plus its trailing CRLF
+            framing_budget = framing_budget.checked_sub(2).ok_or(RequestError::HeaderTooLarge)?;
+            position += size as usize + 2;
+            if position > buffer.len() {
+                return Ok(None);
+            }
+        }
+
+        // Trailer fields run up to the empty line that ends the message
+        loop {
+            let end = match buffered_line(buffer, position, &mut framing_budget)? {
+                Some(end) => end,
+                None => return Ok(None),
+            };
+
+            let line = &buffer[position..end];
+            position = end;
+
+            if line == b"\r\n" || line == b"\n" {
+                return Ok(Some(position));
+            }
+        }
+    }
+}
+
+/// Transfer-Encoding takes precedence over Content-Length (RFC 9112, 6.3)
+fn is_chunked(headers: &Headers) -> bool {
+    headers
+        .get("transfer-encoding")
+        .and_then(|value| value.rsplit(',').next())
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+fn content_length(headers: &Headers) -> u64 {
+    headers
+        .get("content-length")
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0)
+}
+
+/// End offset (past the newline) of the line starting at `start`
+fn next_line(buffer: &[u8], start: usize) -> Option<usize> {
+    buffer
+        .get(start..)?
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map(|offset| start + offset + 1)
+}
+
+/// `read_line` over a buffer: the end of the line starting at `start`,
+/// charged against `budget`, or `None` while it is still incomplete
+fn buffered_line(buffer: &[u8], start: usize, budget: &mut usize) -> Result<Option<usize>, RequestError> {
+    match next_line(buffer, start) {
+        Some(end) if end - start <= *budget => {
+            *budget -= end - start;
+            Ok(Some(end))
+        }
+        Some(_) => Err(RequestError::HeaderTooLarge),
+        None if buffer.len().saturating_sub(start) >= *budget => Err(RequestError::HeaderTooLarge),
+        None => Ok(None),
+    }
+}
+
+fn incomplete_header(buffer: &[u8], limits: &Limits) -> Result<Option<usize>, RequestError> {
+    if buffer.len() >= limits.max_header_size {
+        Err(RequestError::HeaderTooLarge)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads a single line, charging its length against the remaining header budget
+fn read_line<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, budget: &mut usize) -> Result<usize, RequestError> {
+    line.clear();
+
+    if *budget == 0 {
+        return Err(RequestError::HeaderTooLarge);
+    }
+
+    let read = reader.by_ref().take(*budget as u64).read_until(b'\n', line)?;
+    *budget -= read;
+
+    if read > 0 && !line.ends_with(b"\n") && *budget == 0 {
+        return Err(RequestError::HeaderTooLarge);
+    }
+
+    Ok(read)
+}
+
+/// Decodes a chunked body into `body`. Chunk extensions and trailers are ignored,
+/// and the decoded size is held to the same limit as a Content-Length body.
+/// Size lines, CRLFs and trailers share one `max_header_size` budget, as in `frame_length`.
+fn read_chunked_body<R: BufRead>(reader: &mut R, body: &mut Vec<u8>, limits: &Limits) -> Result<(), RequestError> {
+    let mut line = Vec::new();
+    let mut framing_budget = limits.max_header_size;
+
+    loop {
+        if read_line(reader, &mut line, &mut framing_budget)? == 0 {
+            return Err(RequestError::Malformed);
+        }
+
+        let size_line = String::from_utf8_lossy(&line);
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size_hex, 16).map_err(|_| RequestError::Malformed)?;
+
+        if size == 0 {
+            break;
+        }
+
+        if (body.len() as u64).saturating_add(size) > limits.max_body_size {
+            return Err(RequestError::BodyTooLarge);
+        }
+
+        let read = reader.by_ref().take(size).read_to_end(body)?;
+        if read as u64 != size {
+            return Err(RequestError::Malformed);
+        }
+
+        // Every chunk's data is followed by CRLF
+        framing_budget = framing_budget.checked_sub(2).ok_or(RequestError::HeaderTooLarge)?;
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(RequestError::Malformed);
+        }
+    }
+
+    // Skip trailer fields up to the empty line that ends the message
+    loop {
+        if read_line(reader, &mut line, &mut framing_budget)? == 0 {
+            break;
+        }
+
+        if line == b"\r\n" || line == b"\n" {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn limits() -> Limits {
+        Limits {
+            max_header_size: 256,
+            max_header_count: 8,
+            max_body_size: 64,
+            read_timeout: Duration::from_secs(1),
+            write_timeout: Duration::from_secs(1),
+        }
+    }
+
+    fn parse(raw: &[u8]) -> Result<Request, RequestError> {
+        Request::parse(Cursor::new(raw), &limits())
+    }
+
+    const CHUNKED: &[u8] = b"POST /payments HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+
+    fn chunked(rest: &[u8]) -> Vec<u8> {
+        [CHUNKED, rest].concat()
+    }
+
+    /// Two 48 byte chunks, together past the 64 byte body limit
+    fn oversized_chunks() -> Vec<u8> {
+        chunked(&[b"30\r\n".as_slice(), &[b'a'; 48], b"\r\n30\r\n", &[b'a'; 48], b"\r\n0\r\n\r\n"].concat())
+    }
+
+    #[test]
+    fn parses_route_query_and_headers() {
+        let request = parse(b"GET /payments-summary?from=a&to=b HTTP/1.1\r\nX-Rinha-Token: t\r\n\r\n").unwrap();
+
+        assert_eq!(request.route, "GET /payments-summary");
+        assert_eq!(request.params.get("to"), Some("b"));
+        assert_eq!(request.headers.get("x-rinha-token"), Some("t"));
+        assert!(request.body.is_none());
+    }
+
+    #[test]
+    fn limits_the_content_length_body() {
+        let raw = b"POST /payments HTTP/1.1\r\nContent-Length: 65\r\n\r\n";
+
+        assert!(matches!(parse(raw), Err(RequestError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn limits_the_header_size_and_count() {
+        let long = [b"GET / HTTP/1.1\r\nX-Long: ".as_slice(), &[b'a'; 300], b"\r\n\r\n"].concat();
+        let many = [b"GET / HTTP/1.1\r\n".as_slice(), &b"X-A: 1\r\n".repeat(9), b"\r\n"].concat();
+
+        assert!(matches!(parse(&long), Err(RequestError::HeaderTooLarge)));
+        assert!(matches!(parse(&many), Err(RequestError::HeaderTooLarge)));
+    }
+
+    #[test]
+    fn decodes_a_chunked_body_with_extensions_and_trailers() {
+        let raw = chunked(b"6;name=value\r\n{\"amou\r\n6\r\nnt\":1}\r\n0\r\nX-Trailer: ignored\r\n\r\n");
+        let request = parse(&raw).unwrap();
+
+        assert_eq!(request.body, Some(json!({"amount": 1})));
+    }
+
+    #[test]
+    fn prefers_chunked_over_content_length() {
+        let raw = b"POST /payments HTTP/1.1\r\nContent-Length: 100\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n";
+
+        assert_eq!(parse(raw).unwrap().body, Some(json!({})));
+    }
+
+    #[test]
+    fn limits_the_decoded_chunked_body() {
+        let raw = oversized_chunks();
+
+        assert!(matches!(parse(&raw), Err(RequestError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn rejects_malformed_chunks() {
+        assert!(matches!(parse(&chunked(b"zz\r\n")), Err(RequestError::Malformed)));
+        assert!(matches!(parse(&chunked(b"2\r\n{}XX0\r\n\r\n")), Err(RequestError::Malformed)));
+    }
+
+    #[test]
+    fn limits_chunk_size_lines_and_trailers() {
+        let size_line = chunked(&[b'0'; 300]);
+        let trailer = chunked(&[b"0\r\nX-Long: ".as_slice(), &[b'a'; 300], b"\r\n\r\n"].concat());
+
+        assert!(matches!(parse(&size_line), Err(RequestError::HeaderTooLarge)));
+        assert!(matches!(parse(&trailer), Err(RequestError::HeaderTooLarge)));
+    }
+
+    #[test]
+    fn frames_what_parse_accepts() {
+        let requests = [
+            b"GET /payments-summary HTTP/1.1\r\nHost: x\r\n\r\n".to_vec(),
+            b"POST /payments HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}".to_vec(),
+            chunked(b"6;name=value\r\n{\"amou\r\n6\r\nnt\":1}\r\n0\r\nX-Trailer: ignored\r\n\r\n"),
+        ];
+
+        for raw in requests {
+            assert!(parse(&raw).is_ok());
+
+            // Complete only once the last byte is in, and never past it
+            for end in 0..raw.len() {
+                assert!(matches!(Request::frame_length(&raw[..end], &limits()), Ok(None)));
+            }
+
+            let pipelined = [raw.as_slice(), b"GET / HTTP/1.1\r\n"].concat();
+            assert_eq!(Request::frame_length(&pipelined, &limits()).ok().flatten(), Some(raw.len()));
+        }
+    }
+
+    #[test]
+    fn frames_with_the_same_limits_as_parse() {
+        let oversized = [
+            oversized_chunks(),
+            b"POST /payments HTTP/1.1\r\nContent-Length: 65\r\n\r\n".to_vec(),
+            [b"GET / HTTP/1.1\r\nX-Long: ".as_slice(), &[b'a'; 300], b"\r\n\r\n"].concat(),
+            chunked(&[b'0'; 300]),
+            chunked(&[b"0\r\nX-Long: ".as_slice(), &[b'a'; 300], b"\r\n\r\n"].concat()),
+        ];
+
+        for raw in oversized {
+            let framed = Request::frame_length(&raw, &limits());
+            let parsed = parse(&raw);
+
+            assert!(
+                matches!(
+                    (&framed, &parsed),
+                    (Err(RequestError::BodyTooLarge), Err(RequestError::BodyTooLarge))
+                        | (Err(RequestError::HeaderTooLarge), Err(RequestError::HeaderTooLarge))
+                ),
+                "{:?} vs {:?} for {:?}",
+                framed,
+                parsed.map(|request| request.route),
+                String::from_utf8_lossy(&raw)
+            );
+        }
+    }
+
+    #[test]
+    fn caps_the_buffer_at_the_largest_acceptable_request() {
+        assert_eq!(limits().max_request_size(), 256 * 2 + 64);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+/// How many times and how long to keep retrying an operation. Delays grow
+/// exponentially with decorrelated jitter, are capped at `max_delay`, and stop
+/// once the next attempt would start past `deadline`.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Total budget, counted from when the operation started (e.g. requestedAt)
+    pub deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Overrides `defaults` with WORKER_{NAME}_MAX_ATTEMPTS, WORKER_{NAME}_BACKOFF_BASE_MS,
+    /// WORKER_{NAME}_BACKOFF_MAX_MS and WORKER_{NAME}_DEADLINE_MS (0 disables the deadline)
+    pub fn from_env(name: &str, defaults: RetryPolicy) -> Self {
+        let var = |suffix: &str| -> Option<u64> {
+            std::env::var(format!("WORKER_{}_{}", name.to_uppercase(), suffix))
+                .ok()
+                .and_then(|value| value.parse().ok())
+        };
+
+        RetryPolicy {
+            max_attempts: var("MAX_ATTEMPTS").map_or(defaults.max_attempts, |attempts| attempts as usize),
+            base_delay: var("BACKOFF_BASE_MS").map_or(defaults.base_delay, Duration::from_millis),
+            max_delay: var("BACKOFF_MAX_MS").map_or(defaults.max_delay, Duration::from_millis),
+            deadline: match var("DEADLINE_MS") {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => defaults.deadline,
+            },
+        }
+    }
+
+    /// Starts backing off for an operation that began at `started_at`
+    pub fn backoff(&self, started_at: DateTime<Utc>) -> Backoff {
+        self.resume(started_at, 0, self.base_delay)
+    }
+
+    /// Picks up a backoff after `attempts` failed attempts, the last one
+    /// followed by a `previous` delay
+    pub fn resume(&self, started_at: DateTime<Utc>, attempts: usize, previous: Duration) -> Backoff {
+        Backoff {
+            policy: *self,
+            attempts,
+            previous,
+            deadline: self
+                .deadline
+                .and_then(|deadline| chrono::Duration::from_std(deadline).ok())
+                .map(|deadline| started_at + deadline),
+        }
+    }
+
+    /// Runs `operation` until it succeeds or the policy gives up, returning the last error
+    pub fn retry<T, E>(&self, mut operation: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        let mut backoff = self.backoff(Utc::now());
+
+        loop {
+            match operation() {
+                Ok(value) => return Ok(value),
+                Err(e) => match backoff.next_delay() {
+                    Some(delay) => std::thread::sleep(delay),
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+pub struct Backoff {
+    policy: RetryPolicy,
+    attempts: usize,
+    previous: Duration,
+    deadline: Option<DateTime<Utc>>,
+}
+
+impl Backoff {
+    /// Whether no attempt is left, either by count or by deadline
+    pub fn exhausted(&self) -> bool {
+        self.attempts >= self.policy.max_attempts || self.deadline.is_some_and(|deadline| Utc::now() >= deadline)
+    }
+
+    /// Counts a failed attempt and returns how long to wait before the next
+    /// one, or None when the policy gives up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.attempts += 1;
+
+        if self.attempts >= self.policy.max_attempts {
+            return None;
+        }
+
+        // Decorrelated jitter: anywhere between the base and three times the previous delay
+        let base_ms = self.policy.base_delay.as_millis() as u64;
+        let upper_ms = (self.previous.as_millis() as u64).saturating_mul(3).max(base_ms);
+        let delay = Duration::from_millis(base_ms + jitter(upper_ms - base_ms)).min(self.policy.max_delay);
+
+        let past_deadline = self.deadline.is_some_and(|deadline| {
+            chrono::Duration::from_std(delay).is_ok_and(|delay| Utc::now() + delay >= deadline)
+        });
+
+        if past_deadline {
+            return None;
+        }
+
+        self.previous = delay;
+        Some(delay)
+    }
+}
+
+/// A random value in `0..=max`, seeded from the per-instance keys of `RandomState`
+fn jitter(max: u64) -> u64 {
+    if max == 0 {
+        return 0;
+    }
+
+    RandomState::new().hash_one(Instant::now()) % max.saturating_add(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: usize, deadline: Option<Duration>) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(40),
+            deadline,
+        }
+    }
+
+    #[test]
+    fn a_single_attempt_never_waits() {
+        let mut backoff = policy(1, None).backoff(Utc::now());
+
+        assert!(!backoff.exhausted());
+        assert_eq!(backoff.next_delay(), None);
+        assert!(backoff.exhausted());
+    }
+
+    #[test]
+    fn counts_attempts_up_to_the_limit() {
+        let mut backoff = policy(3, None).backoff(Utc::now());
+
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert_eq!(backoff.next_delay(), None);
+    }
+
+    #[test]
+    fn keeps_delays_between_the_base_and_the_cap() {
+        let mut backoff = policy(1000, None).backoff(Utc::now());
+
+        for _ in 0..500 {
+            let delay = backoff.next_delay().unwrap();
+            assert!((Duration::from_millis(10)..=Duration::from_millis(40)).contains(&delay), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn gives_up_when_the_next_attempt_would_pass_the_deadline() {
+        let started_at = Utc::now() - chrono::Duration::milliseconds(995);
+        let mut backoff = policy(10, Some(Duration::from_secs(1))).backoff(started_at);
+
+        assert_eq!(backoff.next_delay(), None);
+    }
+
+    #[test]
+    fn is_exhausted_past_the_deadline() {
+        let started_at = Utc::now() - chrono::Duration::seconds(2);
+
+        assert!(policy(10, Some(Duration::from_secs(1))).backoff(started_at).exhausted());
+        assert!(!policy(10, Some(Duration::from_secs(5))).backoff(started_at).exhausted());
+    }
+
+    #[test]
+    fn resumes_with_the_attempts_already_spent() {
+        let policy = policy(3, None);
+
+        assert!(policy.resume(Utc::now(), 1, Duration::from_millis(10)).next_delay().is_some());
+        assert_eq!(policy.resume(Utc::now(), 2, Duration::from_millis(10)).next_delay(), None);
+        assert!(policy.resume(Utc::now(), 3, Duration::from_millis(10)).exhausted());
+    }
+
+    #[test]
+    fn retries_until_the_operation_succeeds() {
+        let mut calls = 0;
+        let result: Result<usize, ()> = policy(3, None).retry(|| {
+            calls += 1;
+            if calls < 3 { Err(()) } else { Ok(calls) }
+        });
+
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn returns_the_last_error_once_attempts_run_out() {
+        let mut calls = 0;
+        let result: Result<(), usize> = policy(2, None).retry(|| {
+            calls += 1;
+            Err(calls)
+        });
+
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn reads_overrides_and_a_zero_deadline_from_env() {
+        std::env::set_var("WORKER_RETRY_TEST_MAX_ATTEMPTS", "7");
+        std::env::set_var("WORKER_RETRY_TEST_BACKOFF_BASE_MS", "20");
+        std::env::set_var("WORKER_RETRY_TEST_DEADLINE_MS", "0");
+
+        let policy = RetryPolicy::from_env("retry_test", policy(3, Some(Duration::from_secs(1))));
+
+        assert_eq!(policy.max_attempts, 7);
+        assert_eq!(policy.base_delay, Duration::from_millis(20));
+        assert_eq!(policy.max_delay, Duration::from_millis(40));
+        assert_eq!(policy.deadline, None);
+    }
+}
@@ -1,24 +1,235 @@
 pub mod get {
+    use crate::export::{self, Format};
     use crate::request::Request;
-    use crate::store::Store;
-    use crate::redis_pool::ConnectionPool;
-    use serde_json::json;
-    use std::sync::Arc;
-
-    pub fn payments_summary(request: Request, pool: Arc<ConnectionPool>) -> (u16, String) {
-        let store = Store::new(pool);
+    use crate::Response;
+    use crate::store::{self, PageCursor, PaymentFilter, PaymentStore};
+    use crate::reconcile;
+    use crate::timestamp;
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde_json::{json, Value};
 
-        let from = request.params.get("from").map(|s| s.as_str());
-        let to = request.params.get("to").map(|s| s.as_str());
+    pub fn payments_summary<S: PaymentStore>(request: Request, store: &S) -> (u16, String) {
+        let from = request.params.get("from");
+        let to = request.params.get("to");
 
         println!("🐑 Received query params: from={:?}, to={:?}", from, to);
 
-        match store.summary(from, to) {
-            Ok(summary) => (200, summary.to_string()),
-            Err(_) => (500, json!({"error": "Internal Server Error"}).to_string()),
+        let (from, to) = match timestamp::parse_range(from, to) {
+            Ok(range) => range,
+            Err(e) => return (400, json!({"error": e}).to_string()),
+        };
+
+        let grand_total = request.params.get("total") == Some("true");
+
+        let summary = match store.summary(from, to) {
+            Ok(summary) => summary,
+            Err(e) => return super::store_failure(e),
+        };
+
+        // The processor totals are the whole body unless something sits beside them
+        let Some(interval) = request.params.get("interval") else {
+            let body = if grand_total { with_grand_total(summary) } else { summary };
+            return (200, body.to_string());
+        };
+
+        let series = match series(store, interval, from, to, grand_total) {
+            Ok(series) => series,
+            Err(response) => return response,
+        };
+
+        let mut body = nested(summary, grand_total);
+        body["interval"] = json!(interval);
+        body["series"] = series;
+
+        (200, body.to_string())
+    }
+
+    /// One summary per `interval` between `from` and `to`, for dashboards
+    fn series<S: PaymentStore>(
+        store: &S,
+        interval: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        grand_total: bool,
+    ) -> Result<Value, (u16, String)> {
+        let bad_request = |e: String| (400, json!({"error": e}).to_string());
+
+        let interval_ms = timestamp::parse_interval(interval).map_err(bad_request)?;
+        let (Some(from), Some(to)) = (from, to) else {
+            return Err(bad_request("'interval' requires both 'from' and 'to'".to_string()));
+        };
+
+        let max_points: i64 = std::env::var("API_MAX_SERIES_POINTS")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse()
+            .unwrap_or(10000);
+
+        if store::series_len(from, to, interval_ms) > max_points {
+            return Err(bad_request(format!("'interval' is too small, at most {} points per request", max_points)));
+        }
+
+        let series = store
+            .series(from, to, interval_ms)
+            .map_err(super::store_failure)?;
+
+        Ok(series
+            .into_iter()
+            .map(|(start, point)| {
+                let mut point = nested(point, grand_total);
+                point["timestamp"] = json!(start.to_rfc3339_opts(SecondsFormat::Millis, true));
+                point
+            })
+            .collect())
+    }
+
+    /// Nests the processor totals under `summary`, so the fields beside them
+    /// can't clash with a processor name
+    fn nested(summary: Value, grand_total: bool) -> Value {
+        if grand_total {
+            with_grand_total(summary)
+        } else {
+            json!({"summary": summary})
         }
     }
 
+    /// Nests the processor totals under `summary` next to a `total` summing
+    /// them, so no processor name can clash with it
+    fn with_grand_total(summary: Value) -> Value {
+        let processors = summary.as_object().into_iter().flat_map(|processors| processors.values());
+        let (requests, amount) = processors.fold((0, 0.0), |(requests, amount), processor| {
+            (
+                requests + processor["totalRequests"].as_i64().unwrap_or(0),
+                amount + processor["totalAmount"].as_f64().unwrap_or(0.0),
+            )
+        });
+
+        json!({
+            "summary": summary,
+            "total": {
+                "totalRequests": requests,
+                "totalAmount": (amount * 100.0).round() / 100.0
+            }
+        })
+    }
+
+    pub fn reconcile<S: PaymentStore>(request: Request, store: &S) -> (u16, String) {
+        let (from, to) = match timestamp::parse_range(request.params.get("from"), request.params.get("to")) {
+            Ok(range) => range,
+            Err(e) => return (400, json!({"error": e}).to_string()),
+        };
+
+        match reconcile::reconcile(store, from, to) {
+            Ok(report) => (200, report.to_string()),
+            Err(e) => (500, json!({"error": e}).to_string()),
+        }
+    }
+
+    /// Lists payments page by page, filtered by `processor`, `from`/`to` and
+    /// `minAmount`/`maxAmount`; `cursor` comes from the previous page's `nextCursor`
+    pub fn payments<S: PaymentStore>(request: Request, store: &S) -> (u16, String) {
+        let bad_request = |e: String| (400, json!({"error": e}).to_string());
+
+        let (from, to) = match timestamp::parse_range(request.params.get("from"), request.params.get("to")) {
+            Ok(range) => range,
+            Err(e) => return bad_request(e),
+        };
+
+        let amount = |name: &str| {
+            request.params
+                .get(name)
+                .map(|value| value.parse::<f64>().map_err(|_| format!("Invalid '{}': '{}' is not a number", name, value)))
+                .transpose()
+        };
+
+        let (min_amount, max_amount) = match (amount("minAmount"), amount("maxAmount")) {
+            (Ok(Some(min)), Ok(Some(max))) if min > max => {
+                return bad_request("'minAmount' must not be greater than 'maxAmount'".to_string())
+            }
+            (Ok(min), Ok(max)) => (min, max),
+            (Err(e), _) | (_, Err(e)) => return bad_request(e),
+        };
+
+        let cursor = match request.params.get("cursor").map(PageCursor::parse).transpose() {
+            Ok(cursor) => cursor,
+            Err(e) => return bad_request(e),
+        };
+
+        let max_limit: usize = std::env::var("API_MAX_PAGE_SIZE")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse()
+            .unwrap_or(1000);
+
+        let limit = match request.params.get("limit").map(str::parse::<usize>) {
+            None => 100.min(max_limit),
+            Some(Ok(limit)) if (1..=max_limit).contains(&limit) => limit,
+            Some(_) => return bad_request(format!("'limit' must be between 1 and {}", max_limit)),
+        };
+
+        let filter = PaymentFilter {
+            from,
+            to,
+            processor: request.params.get("processor").map(str::to_string),
+            min_amount,
+            max_amount,
+        };
+
+        let page = match store.payments(&filter, cursor, limit) {
+            Ok(page) => page,
+            Err(e) => return super::store_failure(e),
+        };
+
+        let payments: Vec<Value> = page
+            .payments
+            .iter()
+            .map(|payment| {
+                json!({
+                    "correlationId": payment.correlation_id,
+                    "amount": payment.amount,
+                    "processor": payment.processor,
+                    "requestedAt": payment.requested_at.to_rfc3339_opts(SecondsFormat::Millis, true)
+                })
+            })
+            .collect();
+
+        let body = json!({
+            "payments": payments,
+            "nextCursor": page.next.map(|cursor| cursor.to_string())
+        });
+
+        (200, body.to_string())
+    }
+
+    /// Streams the payments between `from` and `to` as CSV or NDJSON
+    pub fn export_payments<S: PaymentStore>(request: Request, store: &S) -> Response<'_> {
+        let (from, to) = match timestamp::parse_range(request.params.get("from"), request.params.get("to")) {
+            Ok(range) => range,
+            Err(e) => return (400, json!({"error": e}).to_string()).into(),
+        };
+
+        let format = match Format::negotiate(request.params.get("format"), request.headers.get("Accept")) {
+            Ok(format) => format,
+            Err(e) => return (400, json!({"error": e}).to_string()).into(),
+        };
+
+        let page_size: usize = std::env::var("API_EXPORT_PAGE_SIZE")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<usize>()
+            .unwrap_or(1000)
+            .max(1);
+
+        let filter = PaymentFilter { from, to, ..PaymentFilter::default() };
+
+        // Read up front, so a failing store still gets a proper 500
+        let first_page = match store.payments(&filter, None, page_size) {
+            Ok(page) => page,
+            Err(e) => return super::store_failure(e).into(),
+        };
+
+        Response::Stream(Box::new(move |writer| {
+            export::stream(store, writer, format, &filter, first_page, page_size)
+        }))
+    }
+
     pub fn not_found() -> (u16, String) {
         (404, json!({"error": "Not Found"}).to_string())
     }
@@ -26,47 +237,207 @@ pub mod get {
 
 pub mod post {
     use crate::request::Request;
-    use crate::store::Store;
-    use crate::redis_pool::ConnectionPool;
+    use crate::store::{PaymentStore, PurgeScope, Reservation};
+    use crate::publisher::Publisher;
+    use crate::timestamp;
     use serde_json::json;
-    use std::sync::Arc;
 
-    pub fn payments(request: Request, pool: Arc<ConnectionPool>) -> (u16, String) {
+    pub fn payments<S: PaymentStore, P: Publisher>(request: Request, store: &S, publisher: &P) -> (u16, String) {
         if let Some(body) = request.body {
             let correlation_id = body["correlationId"].as_str().unwrap_or("");
             let amount = body["amount"].as_f64().unwrap_or(0.0);
 
+            if correlation_id.is_empty() {
+                return (400, json!({"error": "Missing correlationId"}).to_string());
+            }
+
+            let reservation_ttl_secs: u64 = std::env::var("API_IDEMPOTENCY_TTL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .unwrap_or(3600);
+
+            let acknowledgement = json!({"message": "enqueued"}).to_string();
+
+            // Retries of the same payment get the original acknowledgement back,
+            // while reusing a correlationId for a different amount is a conflict
+            match store.reserve(correlation_id, amount, reservation_ttl_secs) {
+                Ok(Reservation::Reserved) => {}
+                Ok(Reservation::Duplicate(Some(original))) if original != amount => {
+                    return (409, json!({"error": "correlationId already used with a different amount"}).to_string());
+                }
+                Ok(Reservation::Duplicate(_)) => return (200, acknowledgement),
+                Err(e) => return super::store_failure(e),
+            }
+
             let payload = json!({
                 "correlationId": correlation_id,
                 "amount": amount,
                 "requestedAt": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
             });
 
-            match pool.get() {
-                Ok(mut conn) => {
-                    match redis::cmd("PUBLISH")
-                        .arg("payments")
-                        .arg(payload.to_string())
-                        .query::<i32>(&mut *conn)
-                    {
-                        Ok(_) => (200, json!({"message": "enqueued"}).to_string()),
-                        Err(_) => (500, json!({"error": "Redis publish failed"}).to_string()),
-                    }
-                },
-                Err(_) => (500, json!({"error": "Redis connection failed"}).to_string()),
+            match publisher.publish(&payload) {
+                Ok(()) => (200, acknowledgement),
+                Err(e) => {
+                    // Free the reservation so the client can retry
+                    let _ = store.release(correlation_id);
+                    (500, json!({"error": e}).to_string())
+                }
             }
         } else {
             (400, json!({"error": "Invalid request"}).to_string())
         }
     }
 
-    pub fn purge_payments(_request: Request, pool: Arc<ConnectionPool>) -> (u16, String) {
-        let store = Store::new(pool);
+    /// Purges everything, or only the payments matching `from`, `to` and `processor`
+    pub fn purge_payments<S: PaymentStore>(request: Request, store: &S) -> (u16, String) {
+        let (from, to) = match timestamp::parse_range(request.params.get("from"), request.params.get("to")) {
+            Ok(range) => range,
+            Err(e) => return (400, json!({"error": e}).to_string()),
+        };
+
+        let scope = PurgeScope {
+            from,
+            to,
+            processor: request.params.get("processor").map(str::to_string),
+        };
 
-        match store.purge_all() {
+        match store.purge(&scope) {
             Ok(_) => (200, json!({"message": "purged"}).to_string()),
-            Err(_) => (500, json!({"error": "Internal Server Error"}).to_string()),
+            Err(e) => super::store_failure(e),
         }
     }
 }
 
+/// Client errors from the store become a 400, anything else a 500
+fn store_failure(error: crate::store::StoreError) -> (u16, String) {
+    match error {
+        crate::store::StoreError::Invalid(message) => (400, serde_json::json!({"error": message}).to_string()),
+        crate::store::StoreError::Internal(_) => (500, serde_json::json!({"error": "Internal Server Error"}).to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get, post};
+    use crate::headers::Headers;
+    use crate::publisher::Publisher;
+    use crate::query::Query;
+    use crate::request::Request;
+    use crate::store::{MemoryStore, PaymentStore};
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Keeps what was published, so tests can see what reached the workers
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Value>>);
+
+    impl Publisher for Recorder {
+        fn publish(&self, payload: &Value) -> Result<(), String> {
+            self.0.lock().unwrap().push(payload.clone());
+            Ok(())
+        }
+    }
+
+    impl Recorder {
+        fn published(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+    }
+
+    fn payment(body: Value) -> Request {
+        Request {
+            route: "POST /payments".to_string(),
+            params: Query::default(),
+            headers: Headers::default(),
+            body: Some(body),
+        }
+    }
+
+    fn summary(query: &str) -> Request {
+        Request {
+            route: "GET /payments-summary".to_string(),
+            params: Query::parse(query),
+            headers: Headers::default(),
+            body: None,
+        }
+    }
+
+    const CORRELATION_ID: &str = "4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b3";
+
+    #[test]
+    fn enqueues_a_new_payment() {
+        let store = MemoryStore::new();
+        let publisher = Recorder::default();
+
+        let (status, _) = post::payments(payment(json!({"correlationId": CORRELATION_ID, "amount": 19.9})), &store, &publisher);
+
+        assert_eq!(status, 200);
+        assert_eq!(publisher.published(), 1);
+    }
+
+    #[test]
+    fn replays_the_acknowledgement_without_enqueuing_twice() {
+        let store = MemoryStore::new();
+        let publisher = Recorder::default();
+        let body = json!({"correlationId": CORRELATION_ID, "amount": 19.9});
+
+        let first = post::payments(payment(body.clone()), &store, &publisher);
+        let replay = post::payments(payment(body), &store, &publisher);
+
+        assert_eq!(first, replay);
+        assert_eq!(publisher.published(), 1);
+    }
+
+    #[test]
+    fn rejects_a_reused_correlation_id_with_another_amount() {
+        let store = MemoryStore::new();
+        let publisher = Recorder::default();
+
+        post::payments(payment(json!({"correlationId": CORRELATION_ID, "amount": 19.9})), &store, &publisher);
+        let (status, _) = post::payments(payment(json!({"correlationId": CORRELATION_ID, "amount": 20.0})), &store, &publisher);
+
+        assert_eq!(status, 409);
+        assert_eq!(publisher.published(), 1);
+    }
+
+    #[test]
+    fn rejects_a_payment_without_correlation_id() {
+        let store = MemoryStore::new();
+        let publisher = Recorder::default();
+
+        let (status, _) = post::payments(payment(json!({"amount": 19.9})), &store, &publisher);
+
+        assert_eq!(status, 400);
+        assert_eq!(publisher.published(), 0);
+    }
+
+    #[test]
+    fn nests_processors_beside_the_grand_total() {
+        let store = MemoryStore::new();
+        store.save(CORRELATION_ID, "total", 19.9, "2025-07-15T12:00:00.000Z").unwrap();
+
+        let (status, body) = get::payments_summary(summary("total=true"), &store);
+        let body: Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(status, 200);
+        assert_eq!(body["summary"]["total"], json!({"totalRequests": 1, "totalAmount": 19.9}));
+        assert_eq!(body["total"], json!({"totalRequests": 1, "totalAmount": 19.9}));
+    }
+
+    #[test]
+    fn nests_processors_beside_the_series() {
+        let store = MemoryStore::new();
+        store.save(CORRELATION_ID, "series", 19.9, "2025-07-15T12:00:00.500Z").unwrap();
+
+        let query = "from=2025-07-15T12:00:00Z&to=2025-07-15T12:00:01.999Z&interval=1s";
+        let (status, body) = get::payments_summary(summary(query), &store);
+        let body: Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(status, 200);
+        assert_eq!(body["summary"]["series"]["totalRequests"], 1);
+        assert_eq!(body["interval"], "1s");
+        assert_eq!(body["series"][0]["timestamp"], "2025-07-15T12:00:00.000Z");
+        assert_eq!(body["series"][0]["summary"]["series"]["totalRequests"], 1);
+        assert_eq!(body["series"][1]["summary"]["default"]["totalRequests"], 0);
+    }
+}
@@ -0,0 +1,165 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::redis_pool::ConnectionPool;
+
+// Pops up to ARGV[2] payloads due by ARGV[1], so concurrent schedulers never
+// take the same retry twice
+const TAKE_DUE_SCRIPT: &str = r#"
+local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+
+if #due > 0 then
+    redis.call('ZREM', KEYS[1], unpack(due))
+end
+
+return due
+"#;
+
+fn take_due_script() -> &'static redis::Script {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| redis::Script::new(TAKE_DUE_SCRIPT))
+}
+
+/// Holds failed payments until their next attempt is due
+pub trait RetryScheduler: Send + Sync {
+    /// Schedules `payload` to be retried at `due` (milliseconds since the epoch)
+    fn schedule(&self, payload: &Value, due: i64) -> Result<(), String>;
+
+    /// Removes and returns up to `limit` payloads due by `now`
+    fn take_due(&self, now: i64, limit: usize) -> Result<Vec<Value>, String>;
+}
+
+/// Retries kept in a Redis sorted set scored by due time, shared by every
+/// worker. The key isn't one of the store's, so purging payments leaves
+/// pending retries alone.
+pub struct RedisScheduler {
+    pool: Arc<ConnectionPool>,
+    key: String,
+}
+
+impl RedisScheduler {
+    pub fn new(pool: Arc<ConnectionPool>) -> Self {
+        let key = std::env::var("WORKER_RETRY_KEY").unwrap_or_else(|_| "retries:payments".to_string());
+        RedisScheduler { pool, key }
+    }
+}
+
+impl RetryScheduler for RedisScheduler {
+    fn schedule(&self, payload: &Value, due: i64) -> Result<(), String> {
+        let mut conn = self.pool.get().map_err(|_| "Redis connection failed".to_string())?;
+
+        redis::cmd("ZADD")
+            .arg(&self.key)
+            .arg(due)
+            .arg(payload.to_string())
+            .query::<i32>(&mut *conn)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to schedule retry: {}", e))
+    }
+
+    fn take_due(&self, now: i64, limit: usize) -> Result<Vec<Value>, String> {
+        let mut conn = self.pool.get().map_err(|_| "Redis connection failed".to_string())?;
+
+        let due: Vec<String> = take_due_script()
+            .key(&self.key)
+            .arg(now)
+            .arg(limit)
+            .invoke(&mut *conn)
+            .map_err(|e| format!("Failed to take due retries: {}", e))?;
+
+        Ok(due.iter().filter_map(|payload| serde_json::from_str(payload).ok()).collect())
+    }
+}
+
+/// In-process retries for embedded mode, lost on restart like the queue itself
+#[derive(Default)]
+pub struct MemoryScheduler {
+    retries: Mutex<BTreeMap<i64, Vec<Value>>>,
+}
+
+impl RetryScheduler for MemoryScheduler {
+    fn schedule(&self, payload: &Value, due: i64) -> Result<(), String> {
+        let mut retries = self.retries.lock().map_err(|_| "Retry scheduler lock poisoned".to_string())?;
+        retries.entry(due).or_default().push(payload.clone());
+        Ok(())
+    }
+
+    fn take_due(&self, now: i64, limit: usize) -> Result<Vec<Value>, String> {
+        let mut retries = self.retries.lock().map_err(|_| "Retry scheduler lock poisoned".to_string())?;
+        let mut due = Vec::new();
+
+        while due.len() < limit {
+            let Some(mut entry) = retries.first_entry() else {
+                break;
+            };
+
+            if *entry.key() > now {
+                break;
+            }
+
+            let payloads = entry.get_mut();
+            let take = payloads.len().min(limit - due.len());
+            due.extend(payloads.drain(..take));
+
+            if payloads.is_empty() {
+                entry.remove();
+            }
+        }
+
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scheduler(retries: &[(i64, u64)]) -> MemoryScheduler {
+        let scheduler = MemoryScheduler::default();
+
+        for (due, id) in retries {
+            scheduler.schedule(&json!({"id": id}), *due).unwrap();
+        }
+
+        scheduler
+    }
+
+    fn ids(payloads: Vec<Value>) -> Vec<u64> {
+        payloads.iter().map(|payload| payload["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn leaves_retries_that_are_not_due_yet() {
+        let scheduler = scheduler(&[(10, 1), (20, 2)]);
+
+        assert_eq!(ids(scheduler.take_due(5, 10).unwrap()), Vec::<u64>::new());
+        assert_eq!(ids(scheduler.take_due(15, 10).unwrap()), vec![1]);
+        assert_eq!(ids(scheduler.take_due(20, 10).unwrap()), vec![2]);
+    }
+
+    #[test]
+    fn takes_due_retries_earliest_first() {
+        let scheduler = scheduler(&[(30, 3), (10, 1), (20, 2)]);
+
+        assert_eq!(ids(scheduler.take_due(30, 10).unwrap()), vec![1, 2, 3]);
+        assert!(scheduler.take_due(30, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_every_payload_sharing_a_due_time() {
+        let scheduler = scheduler(&[(10, 1), (10, 2), (10, 3)]);
+
+        assert_eq!(ids(scheduler.take_due(10, 10).unwrap()), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn limits_a_batch_within_a_shared_due_time() {
+        let scheduler = scheduler(&[(10, 1), (10, 2), (10, 3), (20, 4)]);
+
+        assert_eq!(ids(scheduler.take_due(20, 2).unwrap()), vec![1, 2]);
+        assert_eq!(ids(scheduler.take_due(20, 2).unwrap()), vec![3, 4]);
+        assert!(scheduler.take_due(20, 2).unwrap().is_empty());
+    }
+}
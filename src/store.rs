@@ -1,131 +1,394 @@
-use redis::{Commands, RedisResult};
+use chrono::{DateTime, Utc};
 use serde_json::{json, Value};
+use std::fmt;
 use std::sync::Arc;
-use crate::redis_pool::ConnectionPool;
+use std::thread;
+use std::time::Duration;
 
-#[allow(dead_code)]
-pub struct Store {
-    pool: Arc<ConnectionPool>,
+pub mod memory_store;
+pub mod postgres_store;
+pub mod redis_store;
+
+#[allow(unused_imports)]
+pub use memory_store::MemoryStore;
+pub use postgres_store::PostgresStore;
+pub use redis_store::RedisStore;
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// The backend failed, reported to clients as a 500
+    Internal(String),
+    /// The request can't be served as given, reported to clients as a 400
+    Invalid(String),
 }
 
-impl Store {
-    pub fn new(pool: Arc<ConnectionPool>) -> Self {
-        Store { pool }
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Internal(message) | StoreError::Invalid(message) => write!(f, "{}", message),
+        }
     }
+}
 
-    pub fn save(&self, correlation_id: &str, processor: &str, amount: f64, timestamp: &str) -> RedisResult<bool> {
-        let mut conn = self.pool.get()?;
-        
-        // Atomic check-and-set using SETNX (set if not exists)
-        let was_set: bool = conn.set_nx(format!("processed:{}", correlation_id), 1)?;
-        
-        if !was_set {
-            // Already processed by another worker
-            return Ok(false);
-        }
-        
-        // Set expiration and save payment data
-        let payment_data = json!({
-            "processor": processor,
-            "correlationId": correlation_id,
-            "amount": amount,
-            "timestamp": timestamp
-        });
+impl From<redis::RedisError> for StoreError {
+    fn from(error: redis::RedisError) -> Self {
+        StoreError::Internal(error.to_string())
+    }
+}
+
+pub enum Reservation {
+    Reserved,
+    /// Already reserved, with the amount of the original request
+    Duplicate(Option<f64>),
+}
+
+/// A processed payment, as listed by `PaymentStore::payments`
+pub struct Payment {
+    pub correlation_id: String,
+    pub processor: String,
+    pub amount: f64,
+    pub requested_at: DateTime<Utc>,
+}
 
-        let timestamp_score = chrono::DateTime::parse_from_rfc3339(timestamp)
-            .unwrap()
-            .timestamp_millis() as f64 / 1000.0;
+/// Which payments a listing returns; unset bounds don't filter
+#[derive(Default)]
+pub struct PaymentFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub processor: Option<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
 
-        redis::pipe()
-            .atomic()
-            .expire(format!("processed:{}", correlation_id), 3600)
-            .zadd("payments_log", payment_data.to_string(), timestamp_score)
-            .incr(format!("totalRequests:{}", processor), 1)
-            .incr(format!("totalAmount:{}", processor), amount)
-            .query::<()>(&mut *conn)?;
+impl PaymentFilter {
+    pub fn matches(&self, payment: &Payment) -> bool {
+        self.from.is_none_or(|from| payment.requested_at >= from)
+            && self.to.is_none_or(|to| payment.requested_at <= to)
+            && self.processor.as_deref().is_none_or(|processor| processor == payment.processor)
+            && self.min_amount.is_none_or(|min| payment.amount >= min)
+            && self.max_amount.is_none_or(|max| payment.amount <= max)
+    }
+}
 
-        Ok(true)
+/// Where a listing resumes: after the payments at requestedAt `after` (in
+/// milliseconds) that were already examined, `skip` of them, since several
+/// payments can share a millisecond
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageCursor {
+    pub after: i64,
+    pub skip: usize,
+}
+
+impl PageCursor {
+    /// The cursor once the payment at `requested_at` has been examined too
+    pub fn advance(cursor: Option<PageCursor>, requested_at: i64) -> PageCursor {
+        match cursor {
+            Some(cursor) if cursor.after == requested_at => PageCursor { skip: cursor.skip + 1, ..cursor },
+            _ => PageCursor { after: requested_at, skip: 1 },
+        }
     }
 
-    pub fn summary(&self, from: Option<&str>, to: Option<&str>) -> RedisResult<Value> {
-        if from.is_some() || to.is_some() {
-            self.calculate_filtered_summary(from, to)
-        } else {
-            let mut conn = self.pool.get()?;
-            let mut summary = json!({});
+    /// Parses the `{after}.{skip}` form handed out to clients
+    pub fn parse(input: &str) -> Result<PageCursor, String> {
+        input
+            .rsplit_once('.')
+            .and_then(|(after, skip)| Some(PageCursor { after: after.parse().ok()?, skip: skip.parse().ok()? }))
+            .ok_or_else(|| format!("'{}' is not a valid cursor", input))
+    }
+}
+
+impl fmt::Display for PageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.after, self.skip)
+    }
+}
 
-            for processor in &["default", "fallback"] {
-                let total_requests: i64 = conn.get(format!("totalRequests:{}", processor)).unwrap_or(0);
-                let total_amount: f64 = conn.get(format!("totalAmount:{}", processor)).unwrap_or(0.0);
+/// One page of a listing; `next` is None once nothing is left
+pub struct Page {
+    pub payments: Vec<Payment>,
+    pub next: Option<PageCursor>,
+}
 
-                summary[processor] = json!({
-                    "totalRequests": total_requests,
-                    "totalAmount": (total_amount * 100.0).round() / 100.0
-                });
-            }
+/// Payments examined per listing request at most, so a selective filter
+/// can't turn one page into a scan of the whole log
+const SCAN_BUDGET: usize = 10_000;
 
-            Ok(summary)
+/// Collects up to `limit` matching payments from `entries`, which yields the
+/// payments in order starting right at `cursor`
+pub fn paginate(
+    entries: impl Iterator<Item = Payment>,
+    filter: &PaymentFilter,
+    cursor: Option<PageCursor>,
+    limit: usize,
+) -> Page {
+    let mut payments = Vec::new();
+    let mut position = cursor;
+
+    for (scanned, payment) in entries.enumerate() {
+        if payments.len() == limit || scanned == SCAN_BUDGET {
+            return Page { payments, next: position };
+        }
+
+        position = Some(PageCursor::advance(position, payment.requested_at.timestamp_millis()));
+
+        if filter.matches(&payment) {
+            payments.push(payment);
         }
     }
 
-    fn calculate_filtered_summary(&self, from: Option<&str>, to: Option<&str>) -> RedisResult<Value> {
-        let from_score = from
-            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.timestamp_millis() as f64 / 1000.0)
-            .unwrap_or(f64::NEG_INFINITY);
+    Page { payments, next: None }
+}
+
+/// Which payments a purge removes; the default scope removes everything
+#[derive(Default)]
+pub struct PurgeScope {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub processor: Option<String>,
+}
+
+impl PurgeScope {
+    pub fn is_all(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.processor.is_none()
+    }
+
+    /// Whether a payment at `requested_at` (milliseconds) falls in the scope
+    pub fn contains(&self, processor: &str, requested_at: i64) -> bool {
+        self.from.is_none_or(|from| requested_at >= from.timestamp_millis())
+            && self.to.is_none_or(|to| requested_at <= to.timestamp_millis())
+            && self.processor.as_deref().is_none_or(|scoped| scoped == processor)
+    }
+}
+
+/// Where processed payments are recorded and summarized. Handlers and the
+/// worker only depend on this trait, so they run against Redis in production
+/// and against `MemoryStore` without any external service.
+pub trait PaymentStore: Send + Sync {
+    /// Records a payment once; returns false if it had already been processed
+    fn save(&self, correlation_id: &str, processor: &str, amount: f64, timestamp: &str) -> StoreResult<bool>;
+
+    fn summary(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> StoreResult<Value>;
+
+    /// Up to `limit` payments matching `filter`, ordered by requestedAt and
+    /// resuming at `cursor`, which is only meaningful with the same filter
+    fn payments(&self, filter: &PaymentFilter, cursor: Option<PageCursor>, limit: usize) -> StoreResult<Page>;
+
+    /// Deletes the payments in `scope`, or all of the store's data when unscoped
+    fn purge(&self, scope: &PurgeScope) -> StoreResult<()>;
+
+    fn is_processed(&self, correlation_id: &str) -> bool;
+
+    /// Reserves a correlationId at the API edge so duplicates are never published twice
+    fn reserve(&self, correlation_id: &str, amount: f64, ttl_secs: u64) -> StoreResult<Reservation>;
+
+    fn release(&self, correlation_id: &str) -> StoreResult<()>;
+
+    /// Summaries of consecutive `interval_ms` windows covering `from..=to`,
+    /// each labelled with its start
+    fn series(&self, from: DateTime<Utc>, to: DateTime<Utc>, interval_ms: i64) -> StoreResult<Vec<(DateTime<Utc>, Value)>> {
+        series_windows(from, to, interval_ms)
+            .into_iter()
+            .map(|(start, from, to)| Ok((start, self.summary(Some(from), Some(to))?)))
+            .collect()
+    }
+
+    /// Drops per-payment history older than `before`, keeping only the summary
+    /// aggregates; returns how many entries were dropped. Stores that can't
+    /// compact keep everything.
+    fn compact(&self, _before: DateTime<Utc>) -> StoreResult<u64> {
+        Ok(0)
+    }
+}
+
+/// Processors listed in every summary, from PAYMENT_PROCESSORS (comma-separated)
+pub fn configured_processors() -> Vec<String> {
+    std::env::var("PAYMENT_PROCESSORS")
+        .unwrap_or_else(|_| "default,fallback".to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|processor| !processor.is_empty())
+        .map(str::to_string)
+        .collect()
+}
 
-        let to_score = to
-            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.timestamp_millis() as f64 / 1000.0)
-            .unwrap_or(f64::INFINITY);
+/// Builds the `/payments-summary` body from per-processor request counts and
+/// amounts. Configured processors always show up, any other one once it has data.
+pub fn summary_json(totals: impl IntoIterator<Item = (String, i64, f64)>) -> Value {
+    let mut summary = json!({});
 
-        let mut conn = self.pool.get()?;
-        let payments: Vec<String> = conn.zrangebyscore("payments_log", from_score, to_score)?;
+    for processor in configured_processors() {
+        summary[processor] = json!({"totalRequests": 0, "totalAmount": 0.0});
+    }
 
-        let mut summary = json!({
-            "default": {"totalRequests": 0, "totalAmount": 0.0},
-            "fallback": {"totalRequests": 0, "totalAmount": 0.0}
+    for (processor, total_requests, total_amount) in totals {
+        summary[processor] = json!({
+            "totalRequests": total_requests,
+            "totalAmount": (total_amount * 100.0).round() / 100.0
         });
+    }
 
-        for payment_json in payments {
-            if let Ok(payment) = serde_json::from_str::<Value>(&payment_json) {
-                let processor = payment["processor"].as_str().unwrap_or("");
-                let amount = payment["amount"].as_f64().unwrap_or(0.0);
-
-                if let Some(proc_summary) = summary[processor].as_object_mut() {
-                    if let Some(requests) = proc_summary.get_mut("totalRequests") {
-                        *requests = json!(requests.as_i64().unwrap_or(0) + 1);
-                    }
-                    if let Some(total) = proc_summary.get_mut("totalAmount") {
-                        *total = json!(total.as_f64().unwrap_or(0.0) + amount);
-                    }
-                }
-            }
+    summary
+}
+
+/// Splits `from..=to` into windows aligned to multiples of `interval_ms` since
+/// the epoch, as (start, from, to); the first and last are clipped to the range
+pub fn series_windows(from: DateTime<Utc>, to: DateTime<Utc>, interval_ms: i64) -> Vec<(DateTime<Utc>, DateTime<Utc>, DateTime<Utc>)> {
+    let (from_ms, to_ms) = (from.timestamp_millis(), to.timestamp_millis());
+    let datetime = |ms: i64| DateTime::from_timestamp_millis(ms).unwrap_or_default();
+
+    let mut windows = Vec::new();
+    let mut start = from_ms.div_euclid(interval_ms) * interval_ms;
+
+    while start <= to_ms {
+        let end = start + interval_ms - 1;
+        windows.push((datetime(start), datetime(start.max(from_ms)), datetime(end.min(to_ms))));
+        start += interval_ms;
+    }
+
+    windows
+}
+
+/// How many windows `series_windows` splits `from..=to` into
+pub fn series_len(from: DateTime<Utc>, to: DateTime<Utc>, interval_ms: i64) -> i64 {
+    to.timestamp_millis().div_euclid(interval_ms) - from.timestamp_millis().div_euclid(interval_ms) + 1
+}
+
+/// Periodically compacts history older than STORE_RETENTION_SECS; a retention
+/// of 0 (the default) keeps everything and starts nothing
+pub fn spawn_compaction<S: PaymentStore + 'static>(store: Arc<S>) {
+    let retention_secs: i64 = std::env::var("STORE_RETENTION_SECS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .unwrap_or(0);
+
+    let interval_secs: u64 = std::env::var("STORE_COMPACTION_INTERVAL_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .unwrap_or(60);
+
+    if retention_secs <= 0 {
+        return;
+    }
+
+    println!("🐑 Compacting payments older than {}s every {}s", retention_secs, interval_secs);
+
+    thread::spawn(move || loop {
+        let before = Utc::now() - chrono::Duration::seconds(retention_secs);
+
+        match store.compact(before) {
+            Ok(0) => {}
+            Ok(removed) => println!("🐑 Compacted {} payments older than {}", removed, before.to_rfc3339()),
+            Err(e) => eprintln!("🐑 Compaction failed: {}", e),
         }
 
-        for processor in &["default", "fallback"] {
-            if let Some(proc_summary) = summary[processor].as_object_mut() {
-                if let Some(total) = proc_summary.get_mut("totalAmount") {
-                    *total = json!((total.as_f64().unwrap_or(0.0) * 100.0).round() / 100.0);
-                }
-            }
+        thread::sleep(Duration::from_secs(interval_secs));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(requested_at: i64, processor: &str) -> Payment {
+        Payment {
+            correlation_id: format!("{}-{}", processor, requested_at),
+            processor: processor.to_string(),
+            amount: 19.9,
+            requested_at: DateTime::from_timestamp_millis(requested_at).unwrap(),
         }
+    }
+
+    #[test]
+    fn cursor_round_trips_through_its_string_form() {
+        let cursor = PageCursor { after: 1_752_580_800_000, skip: 3 };
 
-        Ok(summary)
+        assert_eq!(cursor.to_string(), "1752580800000.3");
+        assert_eq!(PageCursor::parse(&cursor.to_string()), Ok(cursor));
+        assert_eq!(PageCursor::parse("-5.1"), Ok(PageCursor { after: -5, skip: 1 }));
     }
 
-    pub fn purge_all(&self) -> RedisResult<()> {
-        let mut conn = self.pool.get()?;
-        redis::cmd("FLUSHDB").query::<()>(&mut *conn)?;
-        Ok(())
+    #[test]
+    fn cursor_rejects_malformed_input() {
+        for input in ["", "12", "a.1", "12.b", "12.-1", "1.2.3"] {
+            assert!(PageCursor::parse(input).is_err(), "{}", input);
+        }
     }
 
-    pub fn is_processed(&self, correlation_id: &str) -> bool {
-        match self.pool.get() {
-            Ok(mut conn) => conn.get::<_, Option<String>>(format!("processed:{}", correlation_id))
-                .unwrap_or(None)
-                .is_some(),
-            Err(_) => false,
+    #[test]
+    fn cursor_counts_payments_sharing_a_millisecond() {
+        let cursor = PageCursor::advance(None, 5);
+        assert_eq!(cursor, PageCursor { after: 5, skip: 1 });
+
+        let cursor = PageCursor::advance(Some(cursor), 5);
+        assert_eq!(cursor, PageCursor { after: 5, skip: 2 });
+
+        assert_eq!(PageCursor::advance(Some(cursor), 6), PageCursor { after: 6, skip: 1 });
+    }
+
+    #[test]
+    fn paginate_stops_at_the_limit_with_a_cursor() {
+        let entries = [1, 2, 2, 3].map(|ms| payment(ms, "default"));
+        let page = paginate(entries.into_iter(), &PaymentFilter::default(), None, 2);
+
+        assert_eq!(page.payments.len(), 2);
+        assert_eq!(page.next, Some(PageCursor { after: 2, skip: 1 }));
+    }
+
+    #[test]
+    fn paginate_has_no_cursor_once_exhausted() {
+        let entries = [1, 2].map(|ms| payment(ms, "default"));
+        let page = paginate(entries.into_iter(), &PaymentFilter::default(), None, 2);
+
+        assert_eq!(page.payments.len(), 2);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn paginate_moves_the_cursor_past_filtered_out_payments() {
+        let entries = [payment(1, "default"), payment(1, "fallback"), payment(1, "default"), payment(2, "fallback")];
+        let filter = PaymentFilter { processor: Some("fallback".to_string()), ..PaymentFilter::default() };
+        let page = paginate(entries.into_iter(), &filter, None, 1);
+
+        assert_eq!(page.payments[0].correlation_id, "fallback-1");
+        assert_eq!(page.next, Some(PageCursor { after: 1, skip: 2 }));
+    }
+
+    #[test]
+    fn paginate_resumes_from_a_cursor() {
+        // Entries start right at the cursor, as the stores hand them over
+        let cursor = PageCursor { after: 2, skip: 1 };
+        let entries = [2, 3].map(|ms| payment(ms, "default"));
+        let page = paginate(entries.into_iter(), &PaymentFilter::default(), Some(cursor), 1);
+
+        assert_eq!(page.next, Some(PageCursor { after: 2, skip: 2 }));
+    }
+
+    #[test]
+    fn memory_store_pages_through_ties_without_gaps_or_repeats() {
+        let store = MemoryStore::new();
+        let timestamps = ["12:00:00.000", "12:00:00.001", "12:00:00.001", "12:00:00.001", "12:00:00.002"];
+
+        for (i, time) in timestamps.iter().enumerate() {
+            let correlation_id = format!("4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b{}", i);
+            store.save(&correlation_id, "default", 1.0, &format!("2025-07-15T{}Z", time)).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let page = store.payments(&PaymentFilter::default(), cursor, 2).unwrap();
+            seen.extend(page.payments.into_iter().map(|payment| payment.correlation_id));
+
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
         }
+
+        let expected: Vec<String> = (0..5).map(|i| format!("4a7901b8-7d26-4d9d-aa19-4dc1c7cf60b{}", i)).collect();
+        assert_eq!(seen, expected);
     }
-}
\ No newline at end of file
+}
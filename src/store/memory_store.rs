@@ -0,0 +1,346 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::store::{paginate, summary_json, Page, PageCursor, Payment, PaymentFilter, PaymentStore, PurgeScope, Reservation, StoreError, StoreResult};
+
+/// Thread-safe store kept entirely in process memory, mirroring `RedisStore`.
+/// With persistence, every saved payment is also appended to a file as one
+/// JSON line and replayed on startup, so summaries survive restarts.
+pub struct MemoryStore {
+    state: Mutex<State>,
+    dedupe_ttl: Option<Duration>,
+}
+
+#[derive(Default)]
+struct State {
+    /// correlationId -> expiry of the processed marker (None keeps it forever)
+    processed: HashMap<String, Option<Instant>>,
+    /// correlationId -> (amount, expiry) of API edge reservations
+    reserved: HashMap<String, (f64, Instant)>,
+    /// Payments by requestedAt in milliseconds, like the `payments_log` scores
+    payments_log: BTreeMap<i64, Vec<LoggedPayment>>,
+    /// processor -> (totalRequests, totalAmount)
+    totals: HashMap<String, (i64, f64)>,
+    append_only_file: Option<File>,
+    append_only_path: Option<PathBuf>,
+    /// When expired markers and reservations are next swept out
+    next_sweep: Option<Instant>,
+}
+
+/// How often writes sweep expired entries, bounding the maps by what
+/// arrived within one TTL instead of everything ever seen
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+struct LoggedPayment {
+    correlation_id: String,
+    processor: String,
+    amount: f64,
+}
+
+impl State {
+    /// Drops expired processed markers and reservations, at most once per `SWEEP_INTERVAL`
+    fn sweep_expired(&mut self, now: Instant) {
+        if self.next_sweep.is_some_and(|next_sweep| next_sweep > now) {
+            return;
+        }
+
+        self.processed.retain(|_, expiry| expiry.is_none_or(|expiry| expiry > now));
+        self.reserved.retain(|_, (_, expiry)| *expiry > now);
+        self.next_sweep = Some(now + SWEEP_INTERVAL);
+    }
+
+    fn record(&mut self, correlation_id: &str, processor: &str, amount: f64, requested_at: i64, expiry: Option<Instant>) {
+        self.processed.insert(correlation_id.to_string(), expiry);
+        self.payments_log.entry(requested_at).or_default().push(LoggedPayment {
+            correlation_id: correlation_id.to_string(),
+            processor: processor.to_string(),
+            amount,
+        });
+
+        let total = self.totals.entry(processor.to_string()).or_insert((0, 0.0));
+        total.0 += 1;
+        total.1 += amount;
+    }
+
+    /// Rewrites the append-only file with the payments still in the log. The
+    /// new file is written and synced beside the old one, then renamed over
+    /// it, so a crash midway leaves the old file intact.
+    fn rewrite_append_only_file(&mut self) -> StoreResult<()> {
+        let Some(path) = self.append_only_path.clone() else {
+            return Ok(());
+        };
+
+        let io_error = |e: std::io::Error| StoreError::Internal(format!("Append-only file rewrite failed: {}", e));
+
+        let mut temporary_path = path.clone().into_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let mut writer = BufWriter::new(File::create(&temporary_path).map_err(io_error)?);
+
+        for (requested_at, payments) in &self.payments_log {
+            for payment in payments {
+                writeln!(writer, "{}", payment_line(payment, *requested_at)).map_err(io_error)?;
+            }
+        }
+
+        let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        file.sync_all().map_err(io_error)?;
+        fs::rename(&temporary_path, &path).map_err(io_error)?;
+
+        self.append_only_file = Some(OpenOptions::new().append(true).open(&path).map_err(io_error)?);
+
+        Ok(())
+    }
+}
+
+fn payment_line(payment: &LoggedPayment, requested_at: i64) -> Value {
+    json!({
+        "correlationId": payment.correlation_id,
+        "processor": payment.processor,
+        "amount": payment.amount,
+        "requestedAt": requested_at
+    })
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        let dedupe_ttl_secs: u64 = std::env::var("STORE_DEDUPE_TTL_SECS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+
+        MemoryStore {
+            state: Mutex::new(State::default()),
+            dedupe_ttl: (dedupe_ttl_secs > 0).then(|| Duration::from_secs(dedupe_ttl_secs)),
+        }
+    }
+
+    /// Opens (or creates) the append-only file at `path` and replays it
+    pub fn with_persistence(path: &str) -> StoreResult<Self> {
+        let store = Self::new();
+        let io_error = |e: std::io::Error| StoreError::Internal(format!("{}: {}", path, e));
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(io_error)?;
+
+        let mut state = store.lock()?;
+
+        for line in BufReader::new(&file).lines() {
+            let line = line.map_err(io_error)?;
+
+            // A torn last line from a crash mid-write is skipped
+            let Ok(payment) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+
+            state.record(
+                payment["correlationId"].as_str().unwrap_or(""),
+                payment["processor"].as_str().unwrap_or(""),
+                payment["amount"].as_f64().unwrap_or(0.0),
+                payment["requestedAt"].as_i64().unwrap_or(0),
+                None,
+            );
+        }
+
+        state.append_only_file = Some(file);
+        state.append_only_path = Some(PathBuf::from(path));
+        drop(state);
+
+        Ok(store)
+    }
+
+    fn lock(&self) -> StoreResult<std::sync::MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|_| StoreError::Internal("Memory store lock poisoned".to_string()))
+    }
+}
+
+impl PaymentStore for MemoryStore {
+    fn save(&self, correlation_id: &str, processor: &str, amount: f64, timestamp: &str) -> StoreResult<bool> {
+        let requested_at = chrono::DateTime::parse_from_rfc3339(timestamp)
+            .map_err(|e| StoreError::Internal(format!("Invalid timestamp '{}': {}", timestamp, e)))?
+            .timestamp_millis();
+
+        let mut state = self.lock()?;
+        let now = Instant::now();
+        state.sweep_expired(now);
+
+        let already_processed = state
+            .processed
+            .get(correlation_id)
+            .is_some_and(|expiry| expiry.is_none_or(|expiry| expiry > now));
+
+        if already_processed {
+            return Ok(false);
+        }
+
+        // Appended before applying, so memory never holds what the file lacks
+        if let Some(file) = state.append_only_file.as_mut() {
+            let payment = LoggedPayment {
+                correlation_id: correlation_id.to_string(),
+                processor: processor.to_string(),
+                amount,
+            };
+            let line = payment_line(&payment, requested_at);
+
+            writeln!(file, "{}", line).map_err(|e| StoreError::Internal(format!("Append-only file write failed: {}", e)))?;
+        }
+
+        state.record(correlation_id, processor, amount, requested_at, self.dedupe_ttl.map(|ttl| now + ttl));
+
+        Ok(true)
+    }
+
+    fn summary(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> StoreResult<Value> {
+        let state = self.lock()?;
+
+        if from.is_none() && to.is_none() {
+            let totals = state
+                .totals
+                .iter()
+                .map(|(processor, (requests, amount))| (processor.clone(), *requests, *amount));
+
+            return Ok(summary_json(totals));
+        }
+
+        let from = from.map(|dt| dt.timestamp_millis()).unwrap_or(i64::MIN);
+        let to = to.map(|dt| dt.timestamp_millis()).unwrap_or(i64::MAX);
+
+        let mut totals: HashMap<&str, (i64, f64)> = HashMap::new();
+
+        for payment in state.payments_log.range(from..=to).flat_map(|(_, payments)| payments) {
+            let total = totals.entry(&payment.processor).or_insert((0, 0.0));
+            total.0 += 1;
+            total.1 += payment.amount;
+        }
+
+        Ok(summary_json(
+            totals
+                .into_iter()
+                .map(|(processor, (requests, amount))| (processor.to_string(), requests, amount)),
+        ))
+    }
+
+    fn payments(&self, filter: &PaymentFilter, cursor: Option<PageCursor>, limit: usize) -> StoreResult<Page> {
+        let state = self.lock()?;
+
+        let from = cursor
+            .map(|cursor| cursor.after)
+            .or(filter.from.map(|dt| dt.timestamp_millis()))
+            .unwrap_or(i64::MIN);
+        let to = filter.to.map(|dt| dt.timestamp_millis()).unwrap_or(i64::MAX);
+
+        if from > to {
+            return Ok(Page { payments: Vec::new(), next: None });
+        }
+
+        let entries = state
+            .payments_log
+            .range(from..=to)
+            .flat_map(|(requested_at, payments)| payments.iter().map(move |payment| (*requested_at, payment)))
+            .skip(cursor.map_or(0, |cursor| cursor.skip))
+            .map(|(requested_at, payment)| Payment {
+                correlation_id: payment.correlation_id.clone(),
+                processor: payment.processor.clone(),
+                amount: payment.amount,
+                requested_at: DateTime::from_timestamp_millis(requested_at).unwrap_or_default(),
+            });
+
+        Ok(paginate(entries, filter, cursor, limit))
+    }
+
+    fn purge(&self, scope: &PurgeScope) -> StoreResult<()> {
+        let mut state = self.lock()?;
+
+        if !scope.is_all() {
+            let state = &mut *state;
+
+            for (requested_at, payments) in state.payments_log.iter_mut() {
+                payments.retain(|payment| {
+                    if !scope.contains(&payment.processor, *requested_at) {
+                        return true;
+                    }
+
+                    state.processed.remove(&payment.correlation_id);
+                    if let Some(total) = state.totals.get_mut(&payment.processor) {
+                        total.0 -= 1;
+                        total.1 -= payment.amount;
+                    }
+
+                    false
+                });
+            }
+
+            state.payments_log.retain(|_, payments| !payments.is_empty());
+            return state.rewrite_append_only_file();
+        }
+
+        if let Some(file) = &state.append_only_file {
+            file.set_len(0)
+                .map_err(|e| StoreError::Internal(format!("Append-only file truncate failed: {}", e)))?;
+        }
+
+        let append_only_file = state.append_only_file.take();
+        let append_only_path = state.append_only_path.take();
+
+        *state = State {
+            append_only_file,
+            append_only_path,
+            ..State::default()
+        };
+
+        Ok(())
+    }
+
+    fn is_processed(&self, correlation_id: &str) -> bool {
+        let now = Instant::now();
+
+        self.lock().is_ok_and(|state| {
+            state
+                .processed
+                .get(correlation_id)
+                .is_some_and(|expiry| expiry.is_none_or(|expiry| expiry > now))
+        })
+    }
+
+    fn reserve(&self, correlation_id: &str, amount: f64, ttl_secs: u64) -> StoreResult<Reservation> {
+        let mut state = self.lock()?;
+        let now = Instant::now();
+        state.sweep_expired(now);
+
+        if let Some((original, expiry)) = state.reserved.get(correlation_id) {
+            if *expiry > now {
+                return Ok(Reservation::Duplicate(Some(*original)));
+            }
+        }
+
+        state
+            .reserved
+            .insert(correlation_id.to_string(), (amount, now + Duration::from_secs(ttl_secs)));
+
+        Ok(Reservation::Reserved)
+    }
+
+    fn release(&self, correlation_id: &str) -> StoreResult<()> {
+        self.lock()?.reserved.remove(correlation_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use postgres::{Client, NoTls};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::queue::Queue;
+use crate::store::{paginate, series_windows, summary_json, Page, PageCursor, Payment, PaymentFilter, PaymentStore, PurgeScope, Reservation, StoreError, StoreResult};
+
+/// Store backed by the `payments` table from `config/init.sql`. The primary
+/// key on correlationId does the deduplication, so processed markers never expire.
+pub struct PostgresStore {
+    clients: Queue<Client>,
+}
+
+impl From<postgres::Error> for StoreError {
+    fn from(error: postgres::Error) -> Self {
+        match error.as_db_error() {
+            // e.g. a correlationId that isn't a UUID, which the Redis store accepts as is
+            Some(db_error) if *db_error.code() == postgres::error::SqlState::INVALID_TEXT_REPRESENTATION => {
+                StoreError::Invalid(db_error.message().to_string())
+            }
+            Some(db_error) => StoreError::Internal(db_error.message().to_string()),
+            None => StoreError::Internal(error.to_string()),
+        }
+    }
+}
+
+impl PostgresStore {
+    pub fn connect(database_url: &str, size: usize) -> StoreResult<Self> {
+        let clients = Queue::new();
+
+        // Pre-allocate connections
+        for _ in 0..size {
+            clients.push(Client::connect(database_url, NoTls)?);
+        }
+
+        Ok(PostgresStore { clients })
+    }
+
+    /// Runs `f` on a pooled client, returning it to the pool afterwards
+    fn with_client<T>(&self, f: impl FnOnce(&mut Client) -> Result<T, postgres::Error>) -> StoreResult<T> {
+        let mut client = self.clients.pop();
+        let result = f(&mut client);
+        self.clients.push(client);
+
+        Ok(result?)
+    }
+}
+
+impl PaymentStore for PostgresStore {
+    fn save(&self, correlation_id: &str, processor: &str, amount: f64, timestamp: &str) -> StoreResult<bool> {
+        let requested_at = parse_timestamp(timestamp)?;
+
+        let inserted = self.with_client(|client| {
+            client.execute(
+                "INSERT INTO payments (correlationId, amount, processor, requested_at)
+                 VALUES (CAST($1::text AS UUID), $2::float8, $3, $4)
+                 ON CONFLICT (correlationId) DO NOTHING",
+                &[&correlation_id, &amount, &processor, &requested_at],
+            )
+        })?;
+
+        Ok(inserted == 1)
+    }
+
+    fn summary(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> StoreResult<Value> {
+        let from = from.map(|dt| dt.naive_utc());
+        let to = to.map(|dt| dt.naive_utc());
+
+        let rows = self.with_client(|client| {
+            client.query(
+                "SELECT processor, COUNT(*), COALESCE(SUM(amount), 0)::float8
+                 FROM payments
+                 WHERE ($1::timestamp IS NULL OR requested_at >= $1)
+                   AND ($2::timestamp IS NULL OR requested_at <= $2)
+                 GROUP BY processor",
+                &[&from, &to],
+            )
+        })?;
+
+        Ok(summary_json(
+            rows.iter()
+                .map(|row| (row.get::<_, String>(0), row.get::<_, i64>(1), row.get::<_, f64>(2))),
+        ))
+    }
+
+    fn series(&self, from: DateTime<Utc>, to: DateTime<Utc>, interval_ms: i64) -> StoreResult<Vec<(DateTime<Utc>, Value)>> {
+        let rows = self.with_client(|client| {
+            client.query(
+                "SELECT (floor(extract(epoch FROM requested_at) * 1000 / $3::int8) * $3::int8)::int8 AS bucket,
+                        processor, COUNT(*), COALESCE(SUM(amount), 0)::float8
+                 FROM payments
+                 WHERE requested_at >= $1 AND requested_at <= $2
+                 GROUP BY 1, 2",
+                &[&from.naive_utc(), &to.naive_utc(), &interval_ms],
+            )
+        })?;
+
+        let mut buckets: HashMap<i64, Vec<(String, i64, f64)>> = HashMap::new();
+
+        for row in &rows {
+            buckets
+                .entry(row.get(0))
+                .or_default()
+                .push((row.get(1), row.get(2), row.get(3)));
+        }
+
+        Ok(series_windows(from, to, interval_ms)
+            .into_iter()
+            .map(|(start, _, _)| {
+                let totals = buckets.remove(&start.timestamp_millis()).unwrap_or_default();
+                (start, summary_json(totals))
+            })
+            .collect())
+    }
+
+    fn payments(&self, filter: &PaymentFilter, cursor: Option<PageCursor>, limit: usize) -> StoreResult<Page> {
+        let from = match cursor {
+            Some(cursor) => DateTime::from_timestamp_millis(cursor.after).map(|dt| dt.naive_utc()),
+            None => filter.from.map(|dt| dt.naive_utc()),
+        };
+        let to = filter.to.map(|dt| dt.naive_utc());
+        let skip = cursor.map_or(0, |cursor| cursor.skip) as i64;
+
+        // Filtering in SQL makes the cursor count matching rows only; one extra
+        // row tells whether another page follows
+        let rows = self.with_client(|client| {
+            client.query(
+                "SELECT correlationId::text, processor, amount::float8, requested_at
+                 FROM payments
+                 WHERE ($1::timestamp IS NULL OR requested_at >= $1)
+                   AND ($2::timestamp IS NULL OR requested_at <= $2)
+                   AND ($3::text IS NULL OR processor = $3)
+                   AND ($4::float8 IS NULL OR amount >= $4)
+                   AND ($5::float8 IS NULL OR amount <= $5)
+                 ORDER BY requested_at, correlationId
+                 OFFSET $6 LIMIT $7",
+                &[&from, &to, &filter.processor, &filter.min_amount, &filter.max_amount, &skip, &(limit as i64 + 1)],
+            )
+        })?;
+
+        let entries = rows.iter().map(|row| Payment {
+            correlation_id: row.get(0),
+            processor: row.get(1),
+            amount: row.get(2),
+            requested_at: row.get::<_, NaiveDateTime>(3).and_utc(),
+        });
+
+        Ok(paginate(entries, filter, cursor, limit))
+    }
+
+    fn purge(&self, scope: &PurgeScope) -> StoreResult<()> {
+        if scope.is_all() {
+            return self.with_client(|client| client.batch_execute("TRUNCATE payments, reservations"));
+        }
+
+        let from = scope.from.map(|dt| dt.naive_utc());
+        let to = scope.to.map(|dt| dt.naive_utc());
+
+        self.with_client(|client| {
+            client.execute(
+                "DELETE FROM payments
+                 WHERE ($1::timestamp IS NULL OR requested_at >= $1)
+                   AND ($2::timestamp IS NULL OR requested_at <= $2)
+                   AND ($3::text IS NULL OR processor = $3)",
+                &[&from, &to, &scope.processor],
+            )
+        })?;
+
+        Ok(())
+    }
+
+    fn is_processed(&self, correlation_id: &str) -> bool {
+        self.with_client(|client| {
+            client.query_opt(
+                "SELECT 1 FROM payments WHERE correlationId = CAST($1::text AS UUID)",
+                &[&correlation_id],
+            )
+        })
+        .is_ok_and(|row| row.is_some())
+    }
+
+    fn reserve(&self, correlation_id: &str, amount: f64, ttl_secs: u64) -> StoreResult<Reservation> {
+        self.with_client(|client| {
+            // Takes over the reservation only when it is new or has expired
+            let reserved = client.query_opt(
+                "INSERT INTO reservations (correlationId, amount, expires_at)
+                 VALUES (CAST($1::text AS UUID), $2::float8, NOW() AT TIME ZONE 'UTC' + make_interval(secs => $3::float8))
+                 ON CONFLICT (correlationId) DO UPDATE
+                     SET amount = EXCLUDED.amount, expires_at = EXCLUDED.expires_at
+                     WHERE reservations.expires_at < NOW() AT TIME ZONE 'UTC'
+                 RETURNING 1",
+                &[&correlation_id, &amount, &(ttl_secs as f64)],
+            )?;
+
+            if reserved.is_some() {
+                return Ok(Reservation::Reserved);
+            }
+
+            let original = client.query_opt(
+                "SELECT amount::float8 FROM reservations WHERE correlationId = CAST($1::text AS UUID)",
+                &[&correlation_id],
+            )?;
+
+            Ok(Reservation::Duplicate(original.map(|row| row.get::<_, f64>(0))))
+        })
+    }
+
+    fn release(&self, correlation_id: &str) -> StoreResult<()> {
+        self.with_client(|client| {
+            client.execute(
+                "DELETE FROM reservations WHERE correlationId = CAST($1::text AS UUID)",
+                &[&correlation_id],
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> StoreResult<NaiveDateTime> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|dt| dt.naive_utc())
+        .map_err(|e| StoreError::Internal(format!("Invalid timestamp '{}': {}", timestamp, e)))
+}
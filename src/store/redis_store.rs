@@ -0,0 +1,730 @@
+use chrono::{DateTime, Utc};
+use redis::Commands;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::{Arc, OnceLock};
+use crate::redis_pool::ConnectionPool;
+use crate::store::{
+    configured_processors, paginate, series_windows, summary_json, Page, PageCursor, Payment, PaymentFilter,
+    PaymentStore, PurgeScope, Reservation, StoreError, StoreResult,
+};
+
+// Returns 1 when the payment was saved, 0 when it had already been processed
+const SAVE_SCRIPT: &str = r#"
+if not redis.call('SET', KEYS[1], 1, 'NX') then
+    return 0
+end
+
+-- Markers must outlive any redelivery, otherwise a late retry is counted twice
+local dedupe_ttl = tonumber(ARGV[1])
+if dedupe_ttl > 0 then
+    redis.call('EXPIRE', KEYS[1], dedupe_ttl)
+end
+
+redis.call('INCR', KEYS[3])
+redis.call('INCRBYFLOAT', KEYS[4], ARGV[4])
+redis.call('SADD', KEYS[8], ARGV[6])
+
+-- Pre-aggregate per bucket so filtered summaries don't rescan the log
+redis.call('ZADD', KEYS[5], ARGV[5], ARGV[5])
+redis.call('HINCRBY', KEYS[6], 'requests:' .. ARGV[6], 1)
+redis.call('HINCRBYFLOAT', KEYS[6], 'amount:' .. ARGV[6], ARGV[4])
+
+-- A late payment behind the retention horizon is only kept in its bucket
+local horizon = tonumber(redis.call('GET', KEYS[7]))
+if not horizon or tonumber(ARGV[7]) >= horizon then
+    redis.call('ZADD', KEYS[2], ARGV[3], ARGV[2])
+end
+
+return 1
+"#;
+
+// Sums the buckets fully inside each window, then the payments on the
+// partial edges exactly. Behind the retention horizon (or a compaction in
+// progress) only buckets are left, so a window whose edge falls inside one
+// there is refused with an UNALIGNED error. Returns, per window, a flat list
+// of processor, requests, amount.
+//
+// The bucket hashes it reads aren't passed in KEYS: their names are built
+// from the ARGV prefix, since which buckets a window spans is only known
+// once the script has read the bucket index. That needs a single-node Redis
+// whose ACLs allow the whole prefix; Cluster would refuse the script.
+const SUMMARY_SCRIPT: &str = r#"
+local width = tonumber(ARGV[1])
+local prefix = ARGV[2]
+local horizon = math.max(
+    tonumber(redis.call('GET', KEYS[3])) or -math.huge,
+    tonumber(redis.call('GET', KEYS[4])) or -math.huge
+)
+local totals
+
+local function add(processor, requests, amount)
+    local total = totals[processor] or { 0, 0 }
+    total[1] = total[1] + requests
+    total[2] = total[2] + amount
+    totals[processor] = total
+end
+
+local function bound(ms)
+    if ms == -math.huge then return '-inf' end
+    if ms == math.huge then return '+inf' end
+    return string.format('%.0f', ms)
+end
+
+local function below(ms)
+    if ms == math.huge then return '+inf' end
+    return '(' .. bound(ms)
+end
+
+-- Log scores are seconds, written with full precision by the save path
+local function score(ms)
+    if ms == -math.huge then return '-inf' end
+    if ms == math.huge then return '+inf' end
+    return string.format('%.17g', ms / 1000)
+end
+
+local function add_buckets(buckets, kind, min, max)
+    for _, bucket in ipairs(redis.call('ZRANGEBYSCORE', buckets, min, max)) do
+        local fields = redis.call('HGETALL', prefix .. kind .. bucket)
+
+        for i = 1, #fields, 2 do
+            local field, processor = string.match(fields[i], '^(%a+):(.+)$')
+
+            if field == 'requests' then
+                add(processor, tonumber(fields[i + 1]), 0)
+            elseif field == 'amount' then
+                add(processor, 0, tonumber(fields[i + 1]))
+            end
+        end
+    end
+end
+
+local function add_log(min, max)
+    for _, member in ipairs(redis.call('ZRANGEBYSCORE', KEYS[2], min, max)) do
+        local ok, payment = pcall(cjson.decode, member)
+
+        if ok and payment['processor'] then
+            add(payment['processor'], 1, tonumber(payment['amount']) or 0)
+        end
+    end
+end
+
+local function summarize(from, to)
+    totals = {}
+
+    if from <= to then
+        -- Whole buckets lie within [first, last)
+        local first = math.ceil(from / width) * width
+        local last = math.floor((to + 1) / width) * width
+
+        if (from < first and from < horizon) or (last <= to and last < horizon) then
+            return nil
+        end
+
+        if first < last then
+            add_buckets(KEYS[1], 'bucket:', bound(first), below(last))
+
+            if from < first then
+                add_log(score(from), '(' .. score(first))
+            end
+
+            if last <= to and last < math.huge then
+                add_log(score(last), score(to))
+            end
+        else
+            add_log(score(from), score(to))
+        end
+    end
+
+    -- Lua numbers would be truncated to integers in the reply
+    local reply = {}
+    for processor, total in pairs(totals) do
+        table.insert(reply, processor)
+        table.insert(reply, tostring(total[1]))
+        table.insert(reply, string.format('%.17g', total[2]))
+    end
+
+    return reply
+end
+
+local windows = {}
+for i = 3, #ARGV, 2 do
+    local window = summarize(tonumber(ARGV[i]) or -math.huge, tonumber(ARGV[i + 1]) or math.huge)
+
+    if not window then
+        return redis.error_reply('UNALIGNED window edge behind the retention horizon')
+    end
+
+    table.insert(windows, window)
+end
+
+return windows
+"#;
+
+// Drops one batch of log entries before the cutoff, oldest first, leaving
+// the buckets in place. `compacting_until` covers the batches in flight; the
+// retention horizon only moves once the last one is done. Returns the number
+// of entries dropped.
+const COMPACT_SCRIPT: &str = r#"
+local cutoff = tonumber(ARGV[1])
+local batch_size = tonumber(ARGV[2])
+
+local horizon = tonumber(redis.call('GET', KEYS[2]))
+if horizon and cutoff <= horizon then
+    return 0
+end
+
+-- Another worker may be compacting further ahead, so the marker only grows
+local compacting = tonumber(redis.call('GET', KEYS[3]))
+if not compacting or compacting < cutoff then
+    redis.call('SET', KEYS[3], ARGV[1])
+    compacting = cutoff
+end
+
+local due = redis.call('ZCOUNT', KEYS[1], '-inf', '(' .. string.format('%.17g', cutoff / 1000))
+local removed = math.min(due, batch_size)
+
+if removed > 0 then
+    redis.call('ZREMRANGEBYRANK', KEYS[1], 0, removed - 1)
+end
+
+if removed == due then
+    redis.call('SET', KEYS[2], ARGV[1])
+
+    if compacting <= cutoff then
+        redis.call('DEL', KEYS[3])
+    end
+end
+
+return removed
+"#;
+
+// Removes one batch of log entries in the score range, optionally for a
+// single processor, undoing their counters, buckets and processed markers.
+// The first batch (empty ARGV[10]) also drops whole buckets behind the
+// retention horizon, whose payments are no longer logged, and refuses with an
+// UNALIGNED error a range whose edge falls inside one of them. Returns
+// removed, skipped and fetched entry counts, plus the horizon for later batches.
+//
+// Like SUMMARY_SCRIPT, it touches keys missing from KEYS: the counters,
+// bucket hashes and processed markers of whatever payments the batch finds,
+// named from the ARGV prefix. Single-node Redis only, with ACLs covering
+// the whole prefix.
+const PURGE_SCRIPT: &str = r#"
+local processor = ARGV[5]
+local width = tonumber(ARGV[6])
+local prefix = ARGV[7]
+
+local function matches(name)
+    return processor == '' or name == processor
+end
+
+local function uncount(name, requests, amount)
+    redis.call('DECRBY', prefix .. 'totalRequests:' .. name, requests)
+    redis.call('INCRBYFLOAT', prefix .. 'totalAmount:' .. name, -amount)
+end
+
+local function drop(index, kind, bucket, name, requests, amount)
+    local hash = prefix .. kind .. bucket
+
+    if redis.call('HINCRBY', hash, 'requests:' .. name, -requests) <= 0 then
+        redis.call('HDEL', hash, 'requests:' .. name, 'amount:' .. name)
+    else
+        redis.call('HINCRBYFLOAT', hash, 'amount:' .. name, -amount)
+    end
+
+    if redis.call('HLEN', hash) == 0 then
+        redis.call('ZREM', index, bucket)
+    end
+end
+
+local horizon
+
+if ARGV[10] == '' then
+    horizon = math.max(
+        tonumber(redis.call('GET', KEYS[3])) or -math.huge,
+        tonumber(redis.call('GET', KEYS[4])) or -math.huge
+    )
+
+    local from = tonumber(ARGV[8]) or -math.huge
+    local to = tonumber(ARGV[9]) or math.huge
+
+    if (from > -math.huge and from < horizon and from % width ~= 0) or (to + 1 < horizon and (to + 1) % width ~= 0) then
+        return redis.error_reply('UNALIGNED purge edge behind the retention horizon')
+    end
+
+    -- Buckets starting in [from, min(horizon, to + 1)) are whole and unlogged
+    local max = math.min(horizon, to + 1)
+
+    if max > from then
+        local min = from == -math.huge and '-inf' or string.format('%.0f', from)
+
+        for _, bucket in ipairs(redis.call('ZRANGEBYSCORE', KEYS[2], min, '(' .. string.format('%.0f', max))) do
+            local fields = redis.call('HGETALL', prefix .. 'bucket:' .. bucket)
+            local totals = {}
+
+            for i = 1, #fields, 2 do
+                local field, name = string.match(fields[i], '^(%a+):(.+)$')
+
+                if name and matches(name) then
+                    totals[name] = totals[name] or { 0, 0 }
+                    totals[name][field == 'requests' and 1 or 2] = tonumber(fields[i + 1])
+                end
+            end
+
+            for name, total in pairs(totals) do
+                uncount(name, total[1], total[2])
+                drop(KEYS[2], 'bucket:', bucket, name, total[1], total[2])
+            end
+        end
+    end
+else
+    horizon = tonumber(ARGV[10]) or -math.huge
+end
+
+local entries = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[2], 'WITHSCORES', 'LIMIT', ARGV[3], ARGV[4])
+local removed, skipped = 0, 0
+
+for i = 1, #entries, 2 do
+    local ok, payment = pcall(cjson.decode, entries[i])
+
+    if ok and payment['processor'] and matches(payment['processor']) then
+        local name = payment['processor']
+        local amount = tonumber(payment['amount']) or 0
+        local requested_at = math.floor(tonumber(entries[i + 1]) * 1000 + 0.5)
+        local bucket = string.format('%.0f', math.floor(requested_at / width) * width)
+
+        redis.call('ZREM', KEYS[1], entries[i])
+        if payment['correlationId'] then
+            redis.call('DEL', prefix .. 'processed:' .. payment['correlationId'])
+        end
+
+        -- Not yet compacted away, but its bucket was already dropped above
+        if requested_at >= horizon then
+            uncount(name, 1, amount)
+            drop(KEYS[2], 'bucket:', bucket, name, 1, amount)
+        end
+
+        removed = removed + 1
+    else
+        skipped = skipped + 1
+    end
+end
+
+return { removed, skipped, #entries / 2, horizon > -math.huge and string.format('%.0f', horizon) or 'none' }
+"#;
+
+/// Log entries read per ZRANGEBYSCORE call when listing payments
+const PAGE_BATCH_SIZE: usize = 500;
+
+/// Log entries handled per purge script call, so Redis is never blocked for long
+const PURGE_BATCH_SIZE: usize = 500;
+
+// Every key the store writes, under its prefix. An empty prefix shares the
+// database with other keys, such as the retry scheduler's, so a full purge
+// only unlinks these.
+const STORE_KEYS: &[&str] = &[
+    "payments_log",
+    "payment_buckets",
+    "compacted_until",
+    "compacting_until",
+    "processors",
+];
+const STORE_KEY_FAMILIES: &[&str] = &["processed:", "reserved:", "totalRequests:", "totalAmount:", "bucket:"];
+
+/// Log entries dropped per compaction script call, for the same reason
+const COMPACT_BATCH_SIZE: usize = 5000;
+
+fn save_script() -> &'static redis::Script {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| redis::Script::new(SAVE_SCRIPT))
+}
+
+fn summary_script() -> &'static redis::Script {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| redis::Script::new(SUMMARY_SCRIPT))
+}
+
+fn purge_script() -> &'static redis::Script {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| redis::Script::new(PURGE_SCRIPT))
+}
+
+fn compact_script() -> &'static redis::Script {
+    static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+    SCRIPT.get_or_init(|| redis::Script::new(COMPACT_SCRIPT))
+}
+
+pub struct RedisStore {
+    pool: Arc<ConnectionPool>,
+    /// Prepended to every key, so a purge only touches Ovelha's own data
+    prefix: String,
+    /// How long `processed:{id}` markers are kept; 0 keeps them forever.
+    /// Each marker costs roughly 60-80 bytes of Redis memory, so a permanent
+    /// horizon grows with the number of payments, same as `payments_log`.
+    dedupe_ttl_secs: u64,
+    /// Width of the `bucket:{start}` pre-aggregates. Buckets already written
+    /// keep their old width, so changing it requires a purge. Behind the
+    /// retention horizon they are all that's left, so it is also the finest
+    /// granularity summaries can be asked for there.
+    bucket_ms: i64,
+}
+
+impl RedisStore {
+    pub fn new(pool: Arc<ConnectionPool>) -> Self {
+        let dedupe_ttl_secs: u64 = std::env::var("STORE_DEDUPE_TTL_SECS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .unwrap_or(0);
+
+        let bucket_ms: i64 = std::env::var("STORE_SUMMARY_BUCKET_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse()
+            .unwrap_or(1000)
+            .max(1);
+
+        // Unprefixed by default, so keys written before namespacing stay visible
+        let prefix = std::env::var("STORE_KEY_PREFIX").unwrap_or_default();
+
+        RedisStore { pool, prefix, dedupe_ttl_secs, bucket_ms }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn owns(&self, key: &str) -> bool {
+        key.strip_prefix(self.prefix.as_str()).is_some_and(|name| {
+            STORE_KEYS.contains(&name) || STORE_KEY_FAMILIES.iter().any(|family| name.starts_with(family))
+        })
+    }
+
+    /// Unlinks every store key under the prefix, a SCAN batch at a time
+    fn purge_prefix(&self) -> StoreResult<()> {
+        let mut conn = self.pool.get()?;
+        let pattern = format!("{}*", self.prefix);
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(PURGE_BATCH_SIZE)
+                .query(&mut *conn)?;
+
+            let keys: Vec<String> = keys.into_iter().filter(|key| self.owns(key)).collect();
+
+            if !keys.is_empty() {
+                redis::cmd("UNLINK").arg(&keys).query::<()>(&mut *conn)?;
+            }
+
+            if next == 0 {
+                return Ok(());
+            }
+
+            cursor = next;
+        }
+    }
+
+    fn bucket_start(&self, millis: i64) -> i64 {
+        millis.div_euclid(self.bucket_ms) * self.bucket_ms
+    }
+
+    /// Turns the scripts' UNALIGNED error into a client error naming the granularity
+    fn unaligned(&self, error: redis::RedisError) -> StoreError {
+        if error.code() == Some("UNALIGNED") {
+            return StoreError::Invalid(format!(
+                "Behind the retention horizon, 'from' and 'to' must fall on {} ms boundaries ('to' inclusive)",
+                self.bucket_ms
+            ));
+        }
+
+        error.into()
+    }
+
+    fn calculate_filtered_summary(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> StoreResult<Value> {
+        let millis = |dt: Option<DateTime<Utc>>| dt.map(|dt| dt.timestamp_millis());
+
+        let mut windows = self.summarize(&[(millis(from), millis(to))])?;
+        Ok(windows.remove(0))
+    }
+
+    /// Summarizes every window of milliseconds in a single script call
+    fn summarize(&self, windows: &[(Option<i64>, Option<i64>)]) -> StoreResult<Vec<Value>> {
+        let bound = |ms: Option<i64>| ms.map_or(String::new(), |ms| ms.to_string());
+
+        let mut invocation = summary_script().prepare_invoke();
+        invocation
+            .key(self.key("payment_buckets"))
+            .key(self.key("payments_log"))
+            .key(self.key("compacted_until"))
+            .key(self.key("compacting_until"))
+            .arg(self.bucket_ms)
+            .arg(&self.prefix);
+
+        for (from, to) in windows {
+            invocation.arg(bound(*from)).arg(bound(*to));
+        }
+
+        let mut conn = self.pool.get()?;
+        let reply: Vec<Vec<String>> = invocation.invoke(&mut *conn).map_err(|e| self.unaligned(e))?;
+
+        Ok(reply
+            .iter()
+            .map(|window| {
+                summary_json(window.chunks_exact(3).map(|entry| {
+                    (entry[0].clone(), entry[1].parse().unwrap_or(0), entry[2].parse().unwrap_or(0.0))
+                }))
+            })
+            .collect())
+    }
+}
+
+impl PaymentStore for RedisStore {
+    fn save(&self, correlation_id: &str, processor: &str, amount: f64, timestamp: &str) -> StoreResult<bool> {
+        let mut conn = self.pool.get()?;
+
+        let payment_data = json!({
+            "processor": processor,
+            "correlationId": correlation_id,
+            "amount": amount,
+            "timestamp": timestamp
+        });
+
+        let requested_at = chrono::DateTime::parse_from_rfc3339(timestamp)
+            .unwrap()
+            .timestamp_millis();
+        let bucket = self.bucket_start(requested_at);
+
+        // Dedupe, log insert and counters run as one script, so a crash can
+        // never leave a payment marked processed without being counted.
+        // Script::invoke uses EVALSHA and reloads the script on NOSCRIPT.
+        let saved: i32 = save_script()
+            .key(self.key(&format!("processed:{}", correlation_id)))
+            .key(self.key("payments_log"))
+            .key(self.key(&format!("totalRequests:{}", processor)))
+            .key(self.key(&format!("totalAmount:{}", processor)))
+            .key(self.key("payment_buckets"))
+            .key(self.key(&format!("bucket:{}", bucket)))
+            .key(self.key("compacted_until"))
+            .key(self.key("processors"))
+            .arg(self.dedupe_ttl_secs)
+            .arg(payment_data.to_string())
+            .arg(requested_at as f64 / 1000.0)
+            .arg(amount)
+            .arg(bucket)
+            .arg(processor)
+            .arg(requested_at)
+            .invoke(&mut *conn)?;
+
+        Ok(saved == 1)
+    }
+
+    fn summary(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> StoreResult<Value> {
+        if from.is_some() || to.is_some() {
+            self.calculate_filtered_summary(from, to)
+        } else {
+            let mut conn = self.pool.get()?;
+            let mut totals = Vec::new();
+
+            // Every processor that ever saved a payment, plus the configured ones
+            let mut processors: BTreeSet<String> = conn.smembers(self.key("processors"))?;
+            processors.extend(configured_processors());
+
+            for processor in processors {
+                let total_requests: i64 = conn.get(self.key(&format!("totalRequests:{}", processor))).unwrap_or(0);
+                let total_amount: f64 = conn.get(self.key(&format!("totalAmount:{}", processor))).unwrap_or(0.0);
+
+                totals.push((processor, total_requests, total_amount));
+            }
+
+            Ok(summary_json(totals))
+        }
+    }
+
+    fn payments(&self, filter: &PaymentFilter, cursor: Option<PageCursor>, limit: usize) -> StoreResult<Page> {
+        let score = |ms: i64| (ms as f64 / 1000.0).to_string();
+        let max = filter.to.map_or("+inf".to_string(), |dt| score(dt.timestamp_millis()));
+
+        let mut conn = self.pool.get()?;
+        let mut buffer = VecDeque::new();
+        let mut position = cursor;
+        let mut exhausted = false;
+        let mut error = None;
+
+        // Reads the log a batch at a time, only as far as the page needs
+        let entries = std::iter::from_fn(|| {
+            if buffer.is_empty() && !exhausted {
+                let min = position
+                    .map(|cursor| cursor.after)
+                    .or(filter.from.map(|dt| dt.timestamp_millis()))
+                    .map_or("-inf".to_string(), score);
+
+                let batch: Vec<(String, f64)> = match redis::cmd("ZRANGEBYSCORE")
+                    .arg(self.key("payments_log"))
+                    .arg(min)
+                    .arg(&max)
+                    .arg("WITHSCORES")
+                    .arg("LIMIT")
+                    .arg(position.map_or(0, |cursor| cursor.skip))
+                    .arg(PAGE_BATCH_SIZE)
+                    .query(&mut *conn)
+                {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        error = Some(e);
+                        return None;
+                    }
+                };
+
+                exhausted = batch.len() < PAGE_BATCH_SIZE;
+
+                for (member, score) in batch {
+                    let requested_at = (score * 1000.0).round() as i64;
+                    position = Some(PageCursor::advance(position, requested_at));
+                    buffer.push_back(logged_payment(&member, requested_at));
+                }
+            }
+
+            buffer.pop_front()
+        });
+
+        let page = paginate(entries, filter, cursor, limit);
+
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(page),
+        }
+    }
+
+    fn purge(&self, scope: &PurgeScope) -> StoreResult<()> {
+        if scope.is_all() {
+            return self.purge_prefix();
+        }
+
+        let score = |dt: Option<DateTime<Utc>>, unbounded: &str| {
+            dt.map_or(unbounded.to_string(), |dt| (dt.timestamp_millis() as f64 / 1000.0).to_string())
+        };
+        let bound = |dt: Option<DateTime<Utc>>, unbounded: &str| {
+            dt.map_or(unbounded.to_string(), |dt| dt.timestamp_millis().to_string())
+        };
+
+        let mut conn = self.pool.get()?;
+        let mut offset = 0;
+        // Unknown until the first batch has dropped the buckets behind it
+        let mut horizon = String::new();
+
+        loop {
+            let (_removed, skipped, fetched, batch_horizon): (usize, usize, usize, String) = purge_script()
+                .key(self.key("payments_log"))
+                .key(self.key("payment_buckets"))
+                .key(self.key("compacted_until"))
+                .key(self.key("compacting_until"))
+                .arg(score(scope.from, "-inf"))
+                .arg(score(scope.to, "+inf"))
+                .arg(offset)
+                .arg(PURGE_BATCH_SIZE)
+                .arg(scope.processor.as_deref().unwrap_or(""))
+                .arg(self.bucket_ms)
+                .arg(&self.prefix)
+                .arg(bound(scope.from, "-inf"))
+                .arg(bound(scope.to, "+inf"))
+                .arg(&horizon)
+                .invoke(&mut *conn)
+                .map_err(|e| self.unaligned(e))?;
+
+            // Skipped entries stay in the log, so the next batch starts past them
+            offset += skipped;
+            horizon = batch_horizon;
+
+            if fetched < PURGE_BATCH_SIZE {
+                return Ok(());
+            }
+        }
+    }
+
+    fn reserve(&self, correlation_id: &str, amount: f64, ttl_secs: u64) -> StoreResult<Reservation> {
+        let mut conn = self.pool.get()?;
+        let key = self.key(&format!("reserved:{}", correlation_id));
+
+        let reserved: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(amount)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_secs)
+            .query(&mut *conn)?;
+
+        if reserved.is_some() {
+            return Ok(Reservation::Reserved);
+        }
+
+        let original_amount: Option<f64> = conn.get(&key)?;
+        Ok(Reservation::Duplicate(original_amount))
+    }
+
+    fn release(&self, correlation_id: &str) -> StoreResult<()> {
+        let mut conn = self.pool.get()?;
+        conn.del::<_, ()>(self.key(&format!("reserved:{}", correlation_id)))?;
+        Ok(())
+    }
+
+    fn series(&self, from: DateTime<Utc>, to: DateTime<Utc>, interval_ms: i64) -> StoreResult<Vec<(DateTime<Utc>, Value)>> {
+        let windows = series_windows(from, to, interval_ms);
+        let ranges: Vec<_> = windows
+            .iter()
+            .map(|(_, from, to)| (Some(from.timestamp_millis()), Some(to.timestamp_millis())))
+            .collect();
+
+        let summaries = self.summarize(&ranges)?;
+        Ok(windows.into_iter().map(|(start, _, _)| start).zip(summaries).collect())
+    }
+
+    fn compact(&self, before: DateTime<Utc>) -> StoreResult<u64> {
+        let mut conn = self.pool.get()?;
+        let mut removed = 0;
+
+        // Aligned to a bucket, so every bucket behind the horizon is whole
+        let cutoff = self.bucket_start(before.timestamp_millis());
+
+        loop {
+            let batch: u64 = compact_script()
+                .key(self.key("payments_log"))
+                .key(self.key("compacted_until"))
+                .key(self.key("compacting_until"))
+                .arg(cutoff)
+                .arg(COMPACT_BATCH_SIZE)
+                .invoke(&mut *conn)?;
+
+            removed += batch;
+
+            if batch < COMPACT_BATCH_SIZE as u64 {
+                return Ok(removed);
+            }
+        }
+    }
+
+    fn is_processed(&self, correlation_id: &str) -> bool {
+        match self.pool.get() {
+            Ok(mut conn) => conn.get::<_, Option<String>>(self.key(&format!("processed:{}", correlation_id)))
+                .unwrap_or(None)
+                .is_some(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Reads a `payments_log` member back; unparseable ones are kept with empty
+/// fields, so listings never lose their place in the log
+fn logged_payment(member: &str, requested_at: i64) -> Payment {
+    let payment: Value = serde_json::from_str(member).unwrap_or_default();
+
+    Payment {
+        correlation_id: payment["correlationId"].as_str().unwrap_or("").to_string(),
+        processor: payment["processor"].as_str().unwrap_or("").to_string(),
+        amount: payment["amount"].as_f64().unwrap_or(0.0),
+        requested_at: DateTime::from_timestamp_millis(requested_at).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+pub type Range = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Parses an RFC3339 timestamp, with or without fractional seconds.
+/// Timestamps without a timezone are taken as UTC.
+pub fn parse(input: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+
+    NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S%.f")
+        .map(|naive| naive.and_utc())
+        .map_err(|_| format!("'{}' is not a valid RFC3339 timestamp", input))
+}
+
+/// Parses the optional `from`/`to` pair of a time window and checks its order
+pub fn parse_range(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Range, String> {
+    let from = from
+        .map(parse)
+        .transpose()
+        .map_err(|e| format!("Invalid 'from': {}", e))?;
+    let to = to
+        .map(parse)
+        .transpose()
+        .map_err(|e| format!("Invalid 'to': {}", e))?;
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err("'from' must not be after 'to'".to_string());
+        }
+    }
+
+    Ok((from, to))
+}
+
+/// Parses a duration like `500ms`, `1s`, `5m`, `1h` or `1d` into milliseconds
+pub fn parse_interval(input: &str) -> Result<i64, String> {
+    let invalid = || format!("'{}' is not a valid interval (e.g. 1s, 1m, 1h)", input);
+
+    let split = input.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = input.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+    let unit_ms = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return Err(invalid()),
+    };
+
+    match amount.checked_mul(unit_ms) {
+        Some(interval) if interval > 0 => Ok(interval),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_an_open_range() {
+        assert_eq!(parse_range(None, None), Ok((None, None)));
+    }
+
+    #[test]
+    fn normalizes_offsets_and_missing_timezones_to_utc() {
+        let (from, to) = parse_range(Some("2025-07-15T15:00:00+03:00"), Some("2025-07-15T12:30:00.5")).unwrap();
+
+        assert_eq!(from, Some(parse("2025-07-15T12:00:00Z").unwrap()));
+        assert_eq!(to, Some(parse("2025-07-15T12:30:00.500Z").unwrap()));
+    }
+
+    #[test]
+    fn accepts_equal_bounds() {
+        assert!(parse_range(Some("2025-07-15T12:00:00Z"), Some("2025-07-15T12:00:00Z")).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_reversed_range() {
+        let error = parse_range(Some("2025-07-15T12:00:01Z"), Some("2025-07-15T12:00:00Z")).unwrap_err();
+        assert!(error.contains("'from' must not be after 'to'"));
+    }
+
+    #[test]
+    fn names_the_invalid_bound() {
+        assert!(parse_range(Some("yesterday"), None).unwrap_err().starts_with("Invalid 'from'"));
+        assert!(parse_range(None, Some("2025-13-01T00:00:00Z")).unwrap_err().starts_with("Invalid 'to'"));
+    }
+}